@@ -0,0 +1,22 @@
+use crate::TracedError;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use tracing::Span;
+
+/// Record a backtrace on the `exception.stacktrace` field.
+///
+/// We use the backtrace stored on the error, if it exposes one via
+/// [`TracedError::backtrace`]; otherwise we capture one here, in the middleware.
+pub(crate) fn record_stacktrace(span: &Span, traced_error: Option<&dyn TracedError>) {
+    let stored = traced_error
+        .and_then(|error| error.backtrace())
+        .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured);
+    match stored {
+        Some(backtrace) => {
+            span.record("exception.stacktrace", tracing::field::display(backtrace));
+        }
+        None => {
+            let backtrace = Backtrace::force_capture();
+            span.record("exception.stacktrace", tracing::field::display(backtrace));
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{HeaderName, FORWARDED};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// A range of IP addresses in CIDR notation - e.g. `10.0.0.0/8` or `fd00::/8` - used to
+/// configure trusted proxies via [`TracingLoggerBuilder::trusted_proxy`].
+///
+/// A single address (e.g. `10.1.2.3`) is also accepted: it is treated as a `/32` (or `/128`)
+/// range.
+///
+/// ```rust
+/// use tracing_actix_web::IpRange;
+///
+/// let range: IpRange = "10.0.0.0/8".parse().unwrap();
+/// assert!(range.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!range.contains("192.168.1.1".parse().unwrap()));
+///
+/// assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+/// ```
+///
+/// [`TracingLoggerBuilder::trusted_proxy`]: crate::TracingLoggerBuilder::trusted_proxy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// `true` if `ip` belongs to the range.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V4(_), IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+                Some(ip) => self.contains(IpAddr::V4(ip)),
+                None => false,
+            },
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = usize::from(prefix_len / 8);
+    let remaining_bits = prefix_len % 8;
+    if network[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+    if remaining_bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - remaining_bits);
+    network[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+impl FromStr for IpRange {
+    type Err = InvalidIpRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = match s.split_once('/') {
+            Some((network, prefix_len)) => (network, Some(prefix_len)),
+            None => (s, None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| InvalidIpRange)?;
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| InvalidIpRange)?,
+            None => max_prefix_len,
+        };
+        if prefix_len > max_prefix_len {
+            return Err(InvalidIpRange);
+        }
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// The error returned when parsing an [`IpRange`] from an invalid string.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct InvalidIpRange;
+
+impl fmt::Display for InvalidIpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid IP range: expected an IP address or a range in CIDR notation (e.g. `10.0.0.0/8`)"
+        )
+    }
+}
+
+impl std::error::Error for InvalidIpRange {}
+
+/// The address of the client, resolved according to the trusted proxies.
+pub(crate) struct ClientAddress {
+    pub(crate) address: String,
+    /// `true` if the request carried forwarding headers that we did not trust.
+    pub(crate) is_forwarded_header_ignored: bool,
+}
+
+/// Resolve the address of the client that issued `request`, according to `trusted_proxies`.
+///
+/// If no trusted proxy is configured, `realip_remote_addr` (i.e. `actix-web`'s own
+/// interpretation of the forwarding headers) is used as-is.
+pub(crate) fn client_address(
+    request: &ServiceRequest,
+    trusted_proxies: &[IpRange],
+    realip_remote_addr: Option<&str>,
+) -> ClientAddress {
+    if trusted_proxies.is_empty() {
+        return ClientAddress {
+            address: realip_remote_addr.unwrap_or("").to_owned(),
+            is_forwarded_header_ignored: false,
+        };
+    }
+    resolve_client_address(request, trusted_proxies)
+}
+
+/// Resolve the address of the client that issued `request`.
+///
+/// Forwarding headers (`Forwarded` or, if missing, `X-Forwarded-For`) are walked from the
+/// right - i.e. starting from the hop closest to us - as long as hops are trusted proxies:
+/// the first untrusted hop is the client.
+/// If the TCP peer itself is not a trusted proxy, forwarding headers are ignored altogether.
+fn resolve_client_address(request: &ServiceRequest, trusted_proxies: &[IpRange]) -> ClientAddress {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|range| range.contains(ip));
+    let hops = forwarded_hops(request);
+
+    let Some(peer) = request.peer_addr().map(|addr| addr.ip()) else {
+        return ClientAddress {
+            address: String::new(),
+            is_forwarded_header_ignored: !hops.is_empty(),
+        };
+    };
+    if !is_trusted(peer) {
+        return ClientAddress {
+            address: peer.to_string(),
+            is_forwarded_header_ignored: !hops.is_empty(),
+        };
+    }
+
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        match parse_hop(hop) {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(ip) {
+                    break;
+                }
+            }
+            // Obfuscated or `unknown` identifiers: the last trusted hop is as far as we can go.
+            None => break,
+        }
+    }
+    ClientAddress {
+        address: client.to_string(),
+        is_forwarded_header_ignored: false,
+    }
+}
+
+/// The addresses listed in the forwarding headers of `request`, from the farthest to the
+/// closest hop.
+fn forwarded_hops(request: &ServiceRequest) -> Vec<String> {
+    let headers = request.headers();
+    let forwarded: Vec<String> = headers
+        .get_all(FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for")
+                    .then(|| value.trim().trim_matches('"').to_owned())
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    headers
+        .get_all(HeaderName::from_static("x-forwarded-for"))
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().to_owned())
+        .filter(|hop| !hop.is_empty())
+        .collect()
+}
+
+/// Parse a hop - e.g. `192.0.2.60`, `192.0.2.60:4711`, `2001:db8::17` or `[2001:db8::17]:4711`.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = hop.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    let ip = hop.strip_prefix('[')?.split(']').next()?;
+    ip.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn trusted() -> Vec<IpRange> {
+        vec!["10.0.0.0/8".parse().unwrap()]
+    }
+
+    fn resolve(request: TestRequest) -> ClientAddress {
+        resolve_client_address(&request.to_srv_request(), &trusted())
+    }
+
+    #[test]
+    fn forwarding_headers_are_ignored_if_the_peer_is_not_trusted() {
+        let request = TestRequest::default()
+            .peer_addr("203.0.113.9:4000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "6.6.6.6"));
+        let client_address = resolve(request);
+        assert_eq!(client_address.address, "203.0.113.9");
+        assert!(client_address.is_forwarded_header_ignored);
+    }
+
+    #[test]
+    fn the_peer_is_the_client_if_there_are_no_forwarding_headers() {
+        let request = TestRequest::default().peer_addr("203.0.113.9:4000".parse().unwrap());
+        let client_address = resolve(request);
+        assert_eq!(client_address.address, "203.0.113.9");
+        assert!(!client_address.is_forwarded_header_ignored);
+    }
+
+    #[test]
+    fn trusted_hops_are_skipped_from_the_right() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((
+                "x-forwarded-for",
+                "6.6.6.6, 198.51.100.7, 10.0.0.5, 10.0.0.6",
+            ));
+        let client_address = resolve(request);
+        // `6.6.6.6` was added by `198.51.100.7`, which we don't trust.
+        assert_eq!(client_address.address, "198.51.100.7");
+        assert!(!client_address.is_forwarded_header_ignored);
+    }
+
+    #[test]
+    fn the_farthest_hop_is_the_client_if_all_hops_are_trusted() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "10.0.0.5, 10.0.0.6"));
+        assert_eq!(resolve(request).address, "10.0.0.5");
+    }
+
+    #[test]
+    fn obfuscated_identifiers_stop_the_walk_at_the_last_trusted_hop() {
+        for identifier in ["unknown", "_hidden"] {
+            let request = TestRequest::default()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header((
+                    "forwarded",
+                    format!("for=6.6.6.6, for={identifier}, for=10.0.0.5"),
+                ));
+            assert_eq!(resolve(request).address, "10.0.0.5");
+        }
+    }
+
+    #[test]
+    fn forwarded_takes_precedence_over_x_forwarded_for() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("forwarded", "for=198.51.100.7;proto=https"))
+            .insert_header(("x-forwarded-for", "6.6.6.6"));
+        assert_eq!(resolve(request).address, "198.51.100.7");
+    }
+
+    #[test]
+    fn x_forwarded_for_is_used_if_forwarded_has_no_for_parameter() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("forwarded", "proto=https"))
+            .insert_header(("x-forwarded-for", "198.51.100.7"));
+        assert_eq!(resolve(request).address, "198.51.100.7");
+    }
+
+    #[test]
+    fn forwarded_hops_may_carry_a_port_or_a_bracketed_ipv6_address() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((
+                "forwarded",
+                "for=\"[2001:db8::17]:4711\", for=\"10.0.0.5:8080\"",
+            ));
+        assert_eq!(resolve(request).address, "2001:db8::17");
+    }
+
+    #[test]
+    fn forwarded_hops_are_parsed() {
+        let request = TestRequest::default()
+            .insert_header(("forwarded", "for=192.0.2.60;proto=http;by=203.0.113.43"))
+            .append_header(("forwarded", "For=\"[2001:db8:cafe::17]\", for=unknown"))
+            .to_srv_request();
+        assert_eq!(
+            forwarded_hops(&request),
+            ["192.0.2.60", "[2001:db8:cafe::17]", "unknown"]
+        );
+    }
+
+    #[test]
+    fn hops_are_parsed() {
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+        assert_eq!(parse_hop("192.0.2.60"), ip("192.0.2.60"));
+        assert_eq!(parse_hop("192.0.2.60:4711"), ip("192.0.2.60"));
+        assert_eq!(parse_hop("2001:db8::17"), ip("2001:db8::17"));
+        assert_eq!(parse_hop("[2001:db8::17]"), ip("2001:db8::17"));
+        assert_eq!(parse_hop("[2001:db8::17]:4711"), ip("2001:db8::17"));
+        assert_eq!(parse_hop("unknown"), None);
+        assert_eq!(parse_hop("_hidden"), None);
+    }
+
+    #[test]
+    fn realip_remote_addr_is_used_if_no_proxy_is_trusted() {
+        let request = TestRequest::default()
+            .peer_addr("203.0.113.9:4000".parse().unwrap())
+            .to_srv_request();
+        let client_address = client_address(&request, &[], Some("6.6.6.6"));
+        assert_eq!(client_address.address, "6.6.6.6");
+        assert!(!client_address.is_forwarded_header_ignored);
+    }
+}
@@ -0,0 +1,749 @@
+use crate::traced_error::{downcast, TracedErrorDowncaster};
+use crate::{
+    DefaultRootSpanBuilder, ErrorEventPolicy, IpRange, PathPattern, RedactionPolicy, RequestId,
+    RequestIdGenerator, RootSpanBuilder, SamplingPolicy, SemanticConventions,
+    StatefulRootSpanBuilder, StatelessRootSpanBuilder, StatusClass, StatusClassifier, TracedError,
+    TracingLogger,
+};
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderName;
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpMessage};
+use std::cell::RefCell;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::Level;
+
+/// `TracingLoggerBuilder` lets you configure [`TracingLogger`] at runtime - e.g. using values
+/// read from your configuration files - instead of encoding every knob into a
+/// [`RootSpanBuilder`] type.
+///
+/// You can get one using [`TracingLogger::builder`] or [`TracingLoggerBuilder::new`].
+///
+/// # Usage
+///
+/// ```rust
+/// use actix_web::App;
+/// use tracing_actix_web::{Level, TracingLogger};
+///
+/// let logger = TracingLogger::builder()
+///     .span_level(Level::DEBUG)
+///     .emit_event_on_error(false)
+///     .build();
+///
+/// let app = App::new().wrap(logger);
+/// ```
+///
+/// The configuration is built once and then shared, behind an [`Arc`], by all the middleware
+/// instances spawned by `actix-web` for your workers.
+pub struct TracingLoggerBuilder<RootSpan: ?Sized> {
+    config: Config,
+    root_span_builder: std::marker::PhantomData<RootSpan>,
+}
+
+impl<RootSpan: RootSpanBuilder + 'static> Default for TracingLoggerBuilder<RootSpan> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RootSpan: RootSpanBuilder + 'static> TracingLoggerBuilder<RootSpan> {
+    pub fn new() -> Self {
+        Self {
+            config: Config::new(Box::new(StatelessRootSpanBuilder::<RootSpan>::new())),
+            root_span_builder: Default::default(),
+        }
+    }
+}
+
+impl<RootSpan: ?Sized> TracingLoggerBuilder<RootSpan> {
+    /// Use an instance of a [`StatefulRootSpanBuilder`] to build the root span, instead of
+    /// the [`RootSpanBuilder`] type the builder was created with.
+    pub fn root_span_builder(
+        self,
+        root_span_builder: impl StatefulRootSpanBuilder,
+    ) -> TracingLoggerBuilder<dyn StatefulRootSpanBuilder> {
+        TracingLoggerBuilder {
+            config: Config {
+                root_span_builder: Box::new(root_span_builder),
+                ..self.config
+            },
+            root_span_builder: Default::default(),
+        }
+    }
+
+    /// The level of the root span created by [`root_span!`] when no level is explicitly
+    /// specified - e.g. by [`DefaultRootSpanBuilder`].
+    ///
+    /// It defaults to [`Level::INFO`].
+    ///
+    /// [`root_span!`]: crate::root_span!
+    /// [`DefaultRootSpanBuilder`]: crate::DefaultRootSpanBuilder
+    pub fn span_level(mut self, level: Level) -> Self {
+        self.config.span_level = level;
+        self
+    }
+
+    /// Emit a [`tracing`] event when request processing fails with an error.
+    ///
+    /// It defaults to `true` if the `emit_event_on_error` feature is enabled, `false` otherwise.
+    /// It is a shorthand for [`ErrorEventPolicy::enabled`]: check out
+    /// [`TracingLoggerBuilder::error_event_policy`] to customise the event further.
+    pub fn emit_event_on_error(mut self, enabled: bool) -> Self {
+        self.config.error_event_policy.enabled = enabled;
+        self
+    }
+
+    /// Customise the event emitted when request processing fails with an error - e.g. its
+    /// level for each status code, or its message.
+    ///
+    /// It defaults to [`ErrorEventPolicy::new`].
+    ///
+    /// ```rust
+    /// use actix_web::http::StatusCode;
+    /// use tracing_actix_web::{ErrorEventPolicy, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .error_event_policy(
+    ///         ErrorEventPolicy::new()
+    ///             .enabled(true)
+    ///             .status_level(StatusCode::NOT_FOUND, None),
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn error_event_policy(mut self, policy: ErrorEventPolicy) -> Self {
+        self.config.error_event_policy = policy;
+        self
+    }
+
+    /// Determine which requests count as failures, according to their status code and route:
+    /// it drives the `otel.status_code` field of the root span, the level of the error event,
+    /// backtrace capture and the promotion of failed requests.
+    ///
+    /// It defaults to [`StatusClassifier::new`]: `1xx`, `2xx` and `3xx` responses are
+    /// successes, `4xx` responses are client errors, everything else is a failure.
+    ///
+    /// ```rust
+    /// use actix_web::http::StatusCode;
+    /// use tracing_actix_web::{StatusClass, StatusClassifier, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .status_classifier(
+    ///         StatusClassifier::new().status_class(StatusCode::CONFLICT, StatusClass::Failure),
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn status_classifier(mut self, classifier: StatusClassifier) -> Self {
+        self.config.status_classifier = classifier;
+        self
+    }
+
+    /// Echo the [`RequestId`] of each request back to the caller, using the specified
+    /// response header - e.g. `x-request-id`.
+    ///
+    /// The header is attached to error responses as well, including those built by `actix-web`
+    /// for `Err(actix_web::Error)` outcomes: `TracingLogger` wraps the error in order to
+    /// add the header to its error response. As a consequence, downcasting those errors
+    /// in a middleware registered _before_ `TracingLogger` will not work.  
+    /// The header is left untouched if it has already been set by your handlers.
+    ///
+    /// It is disabled by default.
+    ///
+    /// ```rust
+    /// use actix_web::http::header::HeaderName;
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .request_id_response_header(HeaderName::from_static("x-request-id"))
+    ///     .build();
+    /// ```
+    ///
+    /// [`RequestId`]: crate::RequestId
+    pub fn request_id_response_header(mut self, header_name: HeaderName) -> Self {
+        self.config.request_id_response_header = Some(header_name);
+        self
+    }
+
+    /// Inherit the [`RequestId`] from the specified header of the incoming request - e.g.
+    /// `x-request-id` or `x-correlation-id`, as set by your edge proxy - instead of generating
+    /// a fresh one.
+    ///
+    /// The header value must be either a valid UUID or an opaque string no longer than 128 bytes
+    /// (see [`TracingLoggerBuilder::max_opaque_request_id_len`]): if the header is missing or
+    /// malformed, a new request id is generated.  
+    /// You can call this method more than once: headers are checked in the order they were
+    /// specified and the first valid value is used.
+    ///
+    /// Whether the request id was inherited or generated is recorded in the
+    /// `request_id.inherited` field of the root span.
+    ///
+    /// ```rust
+    /// use actix_web::http::header::HeaderName;
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .inherit_request_id_from(HeaderName::from_static("x-request-id"))
+    ///     .inherit_request_id_from(HeaderName::from_static("x-correlation-id"))
+    ///     .build();
+    /// ```
+    ///
+    /// [`RequestId`]: crate::RequestId
+    pub fn inherit_request_id_from(mut self, header_name: HeaderName) -> Self {
+        self.config.inherit_request_id_from.push(header_name);
+        self
+    }
+
+    /// The maximum length, in bytes, of the request ids that are not UUIDs when inheriting them
+    /// from an incoming request header (see [`TracingLoggerBuilder::inherit_request_id_from`]).
+    /// Set it to `0` to accept UUIDs only.
+    ///
+    /// Inherited ids that are not UUIDs are exposed as opaque strings - see
+    /// [`RequestId::as_str`].  
+    /// It defaults to 128 bytes.
+    ///
+    /// ```rust
+    /// use actix_web::http::header::HeaderName;
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .inherit_request_id_from(HeaderName::from_static("x-request-id"))
+    ///     .max_opaque_request_id_len(64)
+    ///     .build();
+    /// ```
+    ///
+    /// [`RequestId::as_str`]: crate::RequestId::as_str
+    pub fn max_opaque_request_id_len(mut self, max_len: usize) -> Self {
+        self.config.max_opaque_request_id_len = max_len;
+        self
+    }
+
+    /// Use a custom [`RequestIdGenerator`] to generate request ids - e.g. [`UuidV4Generator`],
+    /// `UuidV7Generator` (with the `uuid_v7` feature flag) or your own implementation.
+    ///
+    /// It defaults to [`UuidV4Generator`], or `UuidV7Generator` if the `uuid_v7` feature
+    /// is enabled.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::{TracingLogger, UuidV4Generator};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .request_id_generator(UuidV4Generator)
+    ///     .build();
+    /// ```
+    ///
+    /// [`UuidV4Generator`]: crate::UuidV4Generator
+    pub fn request_id_generator(mut self, generator: impl RequestIdGenerator) -> Self {
+        self.config.request_id_generator = Some(Box::new(generator));
+        self
+    }
+
+    /// Do not trace requests matching `pattern` - e.g. the health checks performed by your
+    /// orchestrator.
+    ///
+    /// Excluded requests get a disabled root span ([`Span::none`]) and no error event is emitted
+    /// for them. They are still assigned a [`RequestId`], and the [`RootSpan`] extractor
+    /// keeps working in their handlers.  
+    /// You can call this method more than once: a request is excluded if it matches any of
+    /// the specified patterns.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::{PathPattern, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .exclude(PathPattern::exact("/health"))
+    ///     .exclude(PathPattern::glob("/internal/**"))
+    ///     .build();
+    /// ```
+    ///
+    /// [`Span::none`]: tracing::Span::none
+    /// [`RequestId`]: crate::RequestId
+    /// [`RootSpan`]: crate::RootSpan
+    pub fn exclude(mut self, pattern: PathPattern) -> Self {
+        self.config.excluded.push(pattern);
+        self
+    }
+
+    /// Only trace a sample of the incoming requests, according to the specified
+    /// [`SamplingPolicy`].
+    ///
+    /// Every request is traced by default.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::{SamplingPolicy, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .sampling(SamplingPolicy::ratio(0.05))
+    ///     .build();
+    /// ```
+    pub fn sampling(mut self, policy: SamplingPolicy) -> Self {
+        self.config.sampling = Some(policy);
+        self
+    }
+
+    /// Emit a `WARN` summary event for every request that failed - i.e. classified as
+    /// [`StatusClass::Failure`] by the configured
+    /// [`status_classifier`](TracingLoggerBuilder::status_classifier) (by default, `5xx`
+    /// responses).
+    ///
+    /// Pair it with a low [`span_level`](TracingLoggerBuilder::span_level) or with
+    /// [`sampling`](TracingLoggerBuilder::sampling) to keep failed requests visible while
+    /// filtering out the root spans of the successful ones.  
+    /// Excluded requests are never promoted.
+    ///
+    /// The event carries all the fields that [`root_span!`](crate::root_span!) records when the
+    /// request comes in (method, route, client address, request id, captured request headers,
+    /// etc.), together with the status code, the duration, `otel.status_code`,
+    /// `sampling.decision`, `exception.message` and `exception.details`.  
+    /// It does **not** carry:
+    ///
+    /// - the fields added by a custom [`RootSpanBuilder`](crate::RootSpanBuilder);
+    /// - the request and response body fields (`http.request.body.size`, etc.);
+    /// - the captured response headers (`http.response.headers`);
+    /// - `exception.chain`, `exception.stacktrace` and the `error.*` fields;
+    /// - `trace_id`: the event is emitted within the root span, use it to correlate the two.
+    ///
+    /// It is disabled by default.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use tracing_actix_web::{Level, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .span_level(Level::DEBUG)
+    ///     .promote_failed_requests(true)
+    ///     .promote_slow_requests(Duration::from_millis(500))
+    ///     .build();
+    /// ```
+    pub fn promote_failed_requests(mut self, enabled: bool) -> Self {
+        self.config.promote_failed_requests = enabled;
+        self
+    }
+
+    /// Emit an `INFO` summary event for every request whose response took longer than
+    /// `threshold` to be produced.
+    ///
+    /// See [`TracingLoggerBuilder::promote_failed_requests`] for the content of the event.
+    ///
+    /// It is disabled by default.
+    pub fn promote_slow_requests(mut self, threshold: Duration) -> Self {
+        self.config.promote_slow_requests = Some(threshold);
+        self
+    }
+
+    /// Record the size and content type of the request body on the root span:
+    ///
+    /// - `http.request.content_length`, the value of the `Content-Length` header;
+    /// - `http.request.content_type`, the value of the `Content-Type` header;
+    /// - `http.request.body.size`, the number of bytes actually read from the request payload
+    ///   by your application. It is left empty if the payload was never read.
+    ///
+    /// The payload of the request is wrapped in order to count the bytes flowing through it,
+    /// which adds a small overhead to every chunk read by your application.
+    ///
+    /// It is disabled by default.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .capture_request_body_metadata(true)
+    ///     .build();
+    /// ```
+    pub fn capture_request_body_metadata(mut self, enabled: bool) -> Self {
+        self.config.capture_request_body_metadata = enabled;
+        self
+    }
+
+    /// Record the specified header of the incoming request on the `http.request.headers` field
+    /// of the root span.
+    ///
+    /// `tracing` requires all field names to be known upfront, therefore headers can't be
+    /// recorded on dedicated `http.request.header.<name>` fields: all captured headers are
+    /// recorded together, as a map from header name to the list of its values - e.g.
+    /// `{"accept-language": ["en-GB"], "x-tenant-id": ["acme"]}`.  
+    /// Values that are not valid UTF-8 are converted lossily.
+    ///
+    /// You can call this method more than once: a header is captured once, no matter how many
+    /// times it is specified. No header is captured by default.
+    ///
+    /// ```rust
+    /// use actix_web::http::header::{HeaderName, ACCEPT_LANGUAGE, CACHE_CONTROL};
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .capture_request_header(HeaderName::from_static("x-tenant-id"))
+    ///     .capture_request_header(ACCEPT_LANGUAGE)
+    ///     .capture_response_header(CACHE_CONTROL)
+    ///     .build();
+    /// ```
+    ///
+    /// If you need a header on a dedicated field - e.g. to filter on it in your backend - declare
+    /// the field using [`root_span!`] in your own [`RootSpanBuilder`] and record it there:
+    ///
+    /// ```rust
+    /// use actix_web::body::MessageBody;
+    /// use actix_web::dev::{ServiceRequest, ServiceResponse};
+    /// use actix_web::Error;
+    /// use tracing::field::Empty;
+    /// use tracing::Span;
+    /// use tracing_actix_web::{root_span, DefaultRootSpanBuilder, RootSpanBuilder, TracingLogger};
+    ///
+    /// pub struct TenantRootSpanBuilder;
+    ///
+    /// impl RootSpanBuilder for TenantRootSpanBuilder {
+    ///     fn on_request_start(request: &ServiceRequest) -> Span {
+    ///         let span = root_span!(request, http.request.header.x_tenant_id = Empty);
+    ///         if let Some(tenant_id) = request.headers().get("x-tenant-id") {
+    ///             span.record(
+    ///                 "http.request.header.x_tenant_id",
+    ///                 String::from_utf8_lossy(tenant_id.as_bytes()).as_ref(),
+    ///             );
+    ///         }
+    ///         span
+    ///     }
+    ///
+    ///     fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+    ///         DefaultRootSpanBuilder::on_request_end(span, outcome);
+    ///     }
+    /// }
+    ///
+    /// let logger = TracingLogger::<TenantRootSpanBuilder>::new();
+    /// ```
+    ///
+    /// [`root_span!`]: crate::root_span!
+    /// [`RootSpanBuilder`]: crate::RootSpanBuilder
+    pub fn capture_request_header(mut self, header_name: HeaderName) -> Self {
+        if !self.config.captured_request_headers.contains(&header_name) {
+            self.config.captured_request_headers.push(header_name);
+        }
+        self
+    }
+
+    /// Record the specified header of the outgoing response on the `http.response.headers`
+    /// field of the root span.
+    ///
+    /// See [`TracingLoggerBuilder::capture_request_header`] for the format of the field.
+    pub fn capture_response_header(mut self, header_name: HeaderName) -> Self {
+        if !self.config.captured_response_headers.contains(&header_name) {
+            self.config.captured_response_headers.push(header_name);
+        }
+        self
+    }
+
+    /// Scrub sensitive data from the request target and the captured headers before they are
+    /// recorded, according to the specified [`RedactionPolicy`].
+    ///
+    /// It defaults to [`RedactionPolicy::new`], which only redacts credentials headers - e.g.
+    /// `authorization` and `cookie`.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::{RedactionPolicy, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .redaction(RedactionPolicy::new().redact_query_param("api_key"))
+    ///     .build();
+    /// ```
+    pub fn redaction(mut self, policy: RedactionPolicy) -> Self {
+        self.config.redaction = policy;
+        self
+    }
+
+    /// The [`SemanticConventions`] that determine the names of the HTTP fields recorded on the
+    /// root span - e.g. `http.method` or `http.request.method`.
+    ///
+    /// It defaults to [`SemanticConventions::Legacy`]. Use [`SemanticConventions::Dup`] to
+    /// record both sets of names while you migrate, or [`SemanticConventions::from_env`] to
+    /// honour the `OTEL_SEMCONV_STABILITY_OPT_IN` environment variable.
+    pub fn semantic_conventions(mut self, semantic_conventions: SemanticConventions) -> Self {
+        self.config.semantic_conventions = semantic_conventions;
+        self
+    }
+
+    /// Trust the `Forwarded`/`X-Forwarded-For` headers set by proxies within the specified
+    /// range of addresses when resolving the client address (`http.client_ip` or
+    /// `client.address`).
+    ///
+    /// By default, `TracingLogger` relies on [`ConnectionInfo::realip_remote_addr`], which
+    /// believes any forwarding header sent by the client.  
+    /// Once at least one trusted proxy is configured, forwarding headers are walked from the
+    /// right - i.e. from the hop closest to your application - only through trusted proxies:
+    /// the first untrusted hop is recorded as the client address. If the TCP peer itself is not
+    /// a trusted proxy, forwarding headers are ignored altogether and the
+    /// `client.forwarded_header_ignored` field is set to `true`.
+    ///
+    /// You can call this method more than once.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .trusted_proxy("10.0.0.0/8".parse().unwrap())
+    ///     .trusted_proxy("fd00::/8".parse().unwrap())
+    ///     .build();
+    /// ```
+    ///
+    /// [`ConnectionInfo::realip_remote_addr`]: actix_web::dev::ConnectionInfo::realip_remote_addr
+    pub fn trusted_proxy(mut self, range: IpRange) -> Self {
+        self.config.trusted_proxies.push(range);
+        self
+    }
+
+    /// Register an error type implementing [`TracedError`], so that [`DefaultRootSpanBuilder`]
+    /// can record its structured details when a request fails with it.
+    ///
+    /// You can call this method more than once, once for each of your error types.
+    ///
+    /// [`DefaultRootSpanBuilder`]: crate::DefaultRootSpanBuilder
+    pub fn traced_error<E: TracedError>(mut self) -> Self {
+        self.config.traced_errors.push(downcast::<E>);
+        self
+    }
+
+    /// The maximum number of causes recorded in the `exception.chain` field when a request
+    /// fails with an error.
+    ///
+    /// The chain is built walking [`std::error::Error::source`], starting from the error the
+    /// request failed with. `actix-web` hides the type of that error, therefore the chain can
+    /// only be reached for [`TracedError`]s registered via [`TracingLoggerBuilder::traced_error`],
+    /// `Box<dyn std::error::Error>` and `std::io::Error`.
+    ///
+    /// It defaults to `8`. Set it to `0` to disable the field altogether.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::{Level, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .exception_chain_max_depth(4)
+    ///     .exception_chain_events(Level::DEBUG)
+    ///     .build();
+    /// ```
+    pub fn exception_chain_max_depth(mut self, max_depth: usize) -> Self {
+        self.config.exception_chain_max_depth = max_depth;
+        self
+    }
+
+    /// Emit an event, at the specified level, for each of the causes recorded in the
+    /// `exception.chain` field - see [`TracingLoggerBuilder::exception_chain_max_depth`].
+    ///
+    /// Each event carries the depth of the cause (`exception.depth`, starting from `1`) and its
+    /// `Display` representation (`exception.message`).
+    pub fn exception_chain_events(mut self, level: Level) -> Self {
+        self.config.exception_chain_events = Some(level);
+        self
+    }
+
+    /// Record a backtrace in the `exception.stacktrace` field when a request fails with an
+    /// error classified as [`StatusClass::Failure`] by the configured
+    /// [`status_classifier`](TracingLoggerBuilder::status_classifier) (by default, `5xx`
+    /// errors).
+    ///
+    /// The backtrace stored on the error is used if it is exposed via
+    /// [`TracedError::backtrace`]; otherwise a backtrace is captured by the middleware when the
+    /// response is ready - it points at the middleware rather than at the origin of the error,
+    /// but it still shows the chain of services the request went through.
+    ///
+    /// Capturing a backtrace is expensive: it is never done for client errors.
+    /// It defaults to `false`.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::TracingLogger;
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .capture_server_error_backtraces(true)
+    ///     .build();
+    /// ```
+    pub fn capture_server_error_backtraces(mut self, capture: bool) -> Self {
+        self.config.capture_server_error_backtraces = capture;
+        self
+    }
+
+    /// Finalise the configuration and get a [`TracingLogger`] middleware.
+    pub fn build(self) -> TracingLogger<RootSpan> {
+        TracingLogger::from_config(Arc::new(self.config))
+    }
+}
+
+/// The runtime configuration of [`TracingLogger`], shared by all its middleware instances.
+pub(crate) struct Config {
+    pub(crate) root_span_builder: Box<dyn StatefulRootSpanBuilder>,
+    pub(crate) span_level: Level,
+    pub(crate) error_event_policy: ErrorEventPolicy,
+    pub(crate) excluded: Vec<PathPattern>,
+    /// `None` if every request should be traced.
+    pub(crate) sampling: Option<SamplingPolicy>,
+    pub(crate) promote_failed_requests: bool,
+    pub(crate) promote_slow_requests: Option<Duration>,
+    pub(crate) capture_request_body_metadata: bool,
+    pub(crate) captured_request_headers: Vec<HeaderName>,
+    pub(crate) captured_response_headers: Vec<HeaderName>,
+    pub(crate) redaction: RedactionPolicy,
+    pub(crate) semantic_conventions: SemanticConventions,
+    pub(crate) trusted_proxies: Vec<IpRange>,
+    pub(crate) traced_errors: Vec<TracedErrorDowncaster>,
+    pub(crate) exception_chain_max_depth: usize,
+    /// `None` if no event should be emitted for the causes of an error.
+    pub(crate) exception_chain_events: Option<Level>,
+    pub(crate) capture_server_error_backtraces: bool,
+    pub(crate) status_classifier: StatusClassifier,
+    pub(crate) request_id_response_header: Option<HeaderName>,
+    pub(crate) inherit_request_id_from: Vec<HeaderName>,
+    pub(crate) max_opaque_request_id_len: usize,
+    /// `None` if the default generator should be used.
+    pub(crate) request_id_generator: Option<Box<dyn RequestIdGenerator>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(Box::new(
+            StatelessRootSpanBuilder::<DefaultRootSpanBuilder>::new(),
+        ))
+    }
+}
+
+impl Config {
+    fn new(root_span_builder: Box<dyn StatefulRootSpanBuilder>) -> Self {
+        Self {
+            root_span_builder,
+            span_level: Level::INFO,
+            error_event_policy: ErrorEventPolicy::new(),
+            excluded: Vec::new(),
+            sampling: None,
+            promote_failed_requests: false,
+            promote_slow_requests: None,
+            capture_request_body_metadata: false,
+            captured_request_headers: Vec::new(),
+            captured_response_headers: Vec::new(),
+            redaction: RedactionPolicy::new(),
+            semantic_conventions: SemanticConventions::default(),
+            trusted_proxies: Vec::new(),
+            traced_errors: Vec::new(),
+            exception_chain_max_depth: 8,
+            exception_chain_events: None,
+            capture_server_error_backtraces: false,
+            status_classifier: StatusClassifier::new(),
+            request_id_response_header: None,
+            inherit_request_id_from: Vec::new(),
+            max_opaque_request_id_len: 128,
+            request_id_generator: None,
+        }
+    }
+
+    /// `true` if the request should not be traced.
+    pub(crate) fn is_excluded(&self, request: &ServiceRequest) -> bool {
+        self.excluded
+            .iter()
+            .any(|pattern| pattern.matches_request(request.request()))
+    }
+
+    /// `true` if failed or slow requests should be promoted to a summary event.
+    pub(crate) fn is_promotion_enabled(&self) -> bool {
+        self.promote_failed_requests || self.promote_slow_requests.is_some()
+    }
+
+    /// Determine the [`RequestId`] of an incoming request, either inheriting it from one
+    /// of its headers or generating a new one.
+    ///
+    /// [`RequestId`]: crate::RequestId
+    pub(crate) fn request_id(&self, request: &ServiceRequest) -> RequestId {
+        self.inherit_request_id_from
+            .iter()
+            .filter_map(|header_name| request.headers().get(header_name))
+            .find_map(|header_value| {
+                RequestId::inherit(header_value, self.max_opaque_request_id_len)
+            })
+            .unwrap_or_else(|| match &self.request_id_generator {
+                Some(generator) => generator.generate(),
+                None => RequestId::generate(),
+            })
+    }
+}
+
+/// The state [`TracingLogger`] attaches to each request it processes.
+///
+/// It is stored in the request extensions, so that [`root_span!`] and [`DefaultRootSpanBuilder`]
+/// can honour the runtime configuration without having to receive it as an argument.
+///
+/// [`root_span!`]: crate::root_span!
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct RequestContext {
+    pub(crate) config: Arc<Config>,
+    /// The route-specific rules of the [`StatusClassifier`] that apply to the request, if any.
+    status_route: Option<usize>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestContext>> = const { RefCell::new(None) };
+}
+
+impl RequestContext {
+    pub(crate) fn new(config: Arc<Config>, request: &ServiceRequest) -> Self {
+        let status_route = config.status_classifier.route(request.request());
+        Self {
+            config,
+            status_route,
+        }
+    }
+
+    /// Classify `status_code` according to the configured [`StatusClassifier`], taking the
+    /// route-specific rules that apply to the request into account.
+    pub(crate) fn status_class(&self, status_code: StatusCode) -> StatusClass {
+        self.config
+            .status_classifier
+            .classify_route(self.status_route, status_code)
+    }
+
+    /// The context stored in the extensions of a request.
+    ///
+    /// Requests that are not processed by [`TracingLogger`] (e.g. in the unit tests of a
+    /// [`RootSpanBuilder`]) get the default configuration.
+    pub(crate) fn of(extensions: &Extensions) -> Self {
+        extensions
+            .get::<RequestContext>()
+            .cloned()
+            .unwrap_or_else(Self::fallback)
+    }
+
+    /// The context of the request `outcome` belongs to.
+    ///
+    /// `Err` outcomes don't carry the request they belong to: [`TracingLogger`] makes the
+    /// context of the request available via [`RequestContext::scope`] instead.  
+    /// `Err` outcomes handled outside of [`TracingLogger`] (e.g. in the unit tests of a
+    /// [`RootSpanBuilder`]) get the default configuration.
+    pub(crate) fn of_outcome<B>(outcome: &Result<ServiceResponse<B>, Error>) -> Self {
+        match outcome {
+            Ok(response) => Self::of(&response.request().extensions()),
+            Err(_) => CURRENT
+                .with(|current| current.borrow().clone())
+                .unwrap_or_else(Self::fallback),
+        }
+    }
+
+    /// Run `f` with `self` set as the context of the request currently being processed on
+    /// this thread - see [`RequestContext::of_outcome`].
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Guard(Option<RequestContext>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+
+        let previous = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+        let _guard = Guard(previous);
+        f()
+    }
+
+    /// The context of requests that are not processed by [`TracingLogger`].
+    fn fallback() -> Self {
+        static DEFAULT: OnceLock<RequestContext> = OnceLock::new();
+        DEFAULT
+            .get_or_init(|| Self {
+                config: Arc::new(Config::default()),
+                status_route: None,
+            })
+            .clone()
+    }
+}
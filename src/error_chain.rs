@@ -0,0 +1,105 @@
+use crate::TracedError;
+use actix_web::Error;
+use std::error::Error as StdError;
+use tracing::{Level, Span};
+
+/// The first cause of `error`, if we can reach it.
+///
+/// `actix_web::Error` does not expose the causes of the error it wraps: we can only
+/// walk the chain for error types we can downcast to - i.e. registered [`TracedError`]s,
+/// `Box<dyn std::error::Error>` and `std::io::Error`.
+pub(crate) fn error_source<'a>(
+    error: &'a Error,
+    traced_error: Option<&'a dyn TracedError>,
+) -> Option<&'a (dyn StdError + 'static)> {
+    if let Some(traced_error) = traced_error {
+        return traced_error.source();
+    }
+    if let Some(error) = error.as_error::<Box<dyn StdError + 'static>>() {
+        return error.source();
+    }
+    if let Some(error) = error.as_error::<std::io::Error>() {
+        return error.source();
+    }
+    None
+}
+
+/// Record the causes of an error, starting from `source`, on the `exception.chain` field -
+/// e.g. `["connection reset by peer", "broken pipe"]` - up to `max_depth` of them.
+///
+/// If `events_level` is set, an event is emitted for each cause as well.
+pub(crate) fn record_exception_chain(
+    span: &Span,
+    source: Option<&(dyn StdError + 'static)>,
+    max_depth: usize,
+    events_level: Option<Level>,
+) {
+    let chain: Vec<String> = std::iter::successors(source, |&cause| cause.source())
+        .take(max_depth)
+        .map(|cause| cause.to_string())
+        .collect();
+    if chain.is_empty() {
+        return;
+    }
+    if let Some(level) = events_level {
+        for (depth, cause) in chain.iter().enumerate() {
+            let depth = depth + 1;
+            match level {
+                Level::TRACE => {
+                    tracing::trace!(exception.depth = depth, exception.message = %cause, "Caused by")
+                }
+                Level::DEBUG => {
+                    tracing::debug!(exception.depth = depth, exception.message = %cause, "Caused by")
+                }
+                Level::INFO => {
+                    tracing::info!(exception.depth = depth, exception.message = %cause, "Caused by")
+                }
+                Level::WARN => {
+                    tracing::warn!(exception.depth = depth, exception.message = %cause, "Caused by")
+                }
+                Level::ERROR => {
+                    tracing::error!(exception.depth = depth, exception.message = %cause, "Caused by")
+                }
+            }
+        }
+    }
+    span.record("exception.chain", tracing::field::debug(chain));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct CheckoutError(std::io::Error);
+
+    impl fmt::Display for CheckoutError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "The payment provider is unavailable")
+        }
+    }
+
+    impl StdError for CheckoutError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    impl ResponseError for CheckoutError {}
+
+    impl TracedError for CheckoutError {}
+
+    #[test]
+    fn the_causes_of_traced_errors_are_reachable() {
+        let error: Error = CheckoutError(std::io::Error::other("connection reset")).into();
+        let traced_error = error
+            .as_error::<CheckoutError>()
+            .map(|error| error as &dyn TracedError);
+        let source = error_source(&error, traced_error).unwrap();
+        assert_eq!(source.to_string(), "connection reset");
+        // `actix_web::Error` hides the causes of the errors it can't downcast.
+        assert!(error_source(&error, None).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::StatusClass;
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use tracing::Level;
+
+/// `ErrorEventPolicy` controls the [`tracing`] event emitted by [`TracingLogger`] when
+/// request processing fails with an error - see [`TracingLoggerBuilder::error_event_policy`].
+///
+/// By default:
+///
+/// - the event is emitted if the `emit_event_on_error` feature is enabled;
+/// - its level is `WARN` for client errors, `ERROR` for everything else - see
+///   [`StatusClassifier`];
+/// - its message is `Error encountered while processing the incoming HTTP request`, followed
+///   by the `Debug` representation of the error.
+///
+/// The event is emitted as well if the request handler panics, at the level used for
+/// [`StatusClass::Failure`](crate::StatusClass::Failure) errors.
+///
+/// # Usage
+///
+/// ```rust
+/// use actix_web::http::StatusCode;
+/// use tracing_actix_web::{ErrorEventPolicy, ErrorFormat, Level, TracingLogger};
+///
+/// let policy = ErrorEventPolicy::new()
+///     // Don't emit an event for 404s...
+///     .status_level(StatusCode::NOT_FOUND, None)
+///     // ...and downgrade 401s to `INFO`
+///     .status_level(StatusCode::UNAUTHORIZED, Some(Level::INFO))
+///     .message("Request failed")
+///     .format(ErrorFormat::Display);
+///
+/// let logger = TracingLogger::builder()
+///     .error_event_policy(policy)
+///     .build();
+/// ```
+///
+/// [`TracingLogger`]: crate::TracingLogger
+/// [`StatusClassifier`]: crate::StatusClassifier
+/// [`TracingLoggerBuilder::error_event_policy`]: crate::TracingLoggerBuilder::error_event_policy
+#[derive(Clone, Debug)]
+pub struct ErrorEventPolicy {
+    pub(crate) enabled: bool,
+    message: String,
+    format: ErrorFormat,
+    client_error_level: Option<Level>,
+    server_error_level: Option<Level>,
+    status_levels: Vec<(StatusCode, Option<Level>)>,
+}
+
+/// How the error is rendered in the event emitted according to an [`ErrorEventPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Use the `Debug` representation of the error.
+    Debug,
+    /// Use the `Display` representation of the error.
+    Display,
+}
+
+impl Default for ErrorEventPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErrorEventPolicy {
+    pub fn new() -> Self {
+        Self {
+            enabled: cfg!(feature = "emit_event_on_error"),
+            message: "Error encountered while processing the incoming HTTP request".into(),
+            format: ErrorFormat::Debug,
+            client_error_level: Some(Level::WARN),
+            server_error_level: Some(Level::ERROR),
+            status_levels: Vec::new(),
+        }
+    }
+
+    /// Whether the event should be emitted at all.
+    ///
+    /// It defaults to `true` if the `emit_event_on_error` feature is enabled, `false` otherwise.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// The message of the event, followed by the representation of the error.
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// How the error is rendered in the event. It defaults to [`ErrorFormat::Debug`].
+    pub fn format(mut self, format: ErrorFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The level of the event for errors classified as [`StatusClass::ClientError`] (by
+    /// default, `4xx` errors) or [`StatusClass::Success`] - `None` to skip the event.
+    ///
+    /// It defaults to `WARN`.
+    ///
+    /// [`StatusClass::ClientError`]: crate::StatusClass::ClientError
+    /// [`StatusClass::Success`]: crate::StatusClass::Success
+    pub fn client_error_level(mut self, level: Option<Level>) -> Self {
+        self.client_error_level = level;
+        self
+    }
+
+    /// The level of the event for errors classified as [`StatusClass::Failure`] (by default,
+    /// `5xx` errors) - `None` to skip the event.
+    ///
+    /// It defaults to `ERROR`.
+    ///
+    /// [`StatusClass::Failure`]: crate::StatusClass::Failure
+    pub fn server_error_level(mut self, level: Option<Level>) -> Self {
+        self.server_error_level = level;
+        self
+    }
+
+    /// The level of the event for errors with the specified status code - `None` to skip
+    /// the event.
+    ///
+    /// It takes precedence over [`ErrorEventPolicy::client_error_level`] and
+    /// [`ErrorEventPolicy::server_error_level`].
+    pub fn status_level(mut self, status_code: StatusCode, level: Option<Level>) -> Self {
+        self.status_levels.push((status_code, level));
+        self
+    }
+
+    fn level(&self, status_code: StatusCode, class: StatusClass) -> Option<Level> {
+        // Later overrides win.
+        if let Some((_, level)) = self
+            .status_levels
+            .iter()
+            .rev()
+            .find(|(code, _)| *code == status_code)
+        {
+            return *level;
+        }
+        match class {
+            StatusClass::Success | StatusClass::ClientError => self.client_error_level,
+            StatusClass::Failure => self.server_error_level,
+        }
+    }
+
+    pub(crate) fn emit(
+        &self,
+        response_error: &dyn ResponseError,
+        status_code: StatusCode,
+        class: StatusClass,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let Some(level) = self.level(status_code, class) else {
+            return;
+        };
+        let error = match self.format {
+            ErrorFormat::Debug => format!("{response_error:?}"),
+            ErrorFormat::Display => format!("{response_error}"),
+        };
+        self.log(level, &error);
+    }
+
+    /// Emit the event for a request whose handler panicked, at the level used for
+    /// [`StatusClass::Failure`].
+    pub(crate) fn emit_panic(&self, panic_message: &str) {
+        if !self.enabled {
+            return;
+        }
+        let Some(level) = self.server_error_level else {
+            return;
+        };
+        self.log(
+            level,
+            &format!("the request handler panicked: {panic_message}"),
+        );
+    }
+
+    fn log(&self, level: Level, error: &str) {
+        // Keep the target the event had before it was made configurable, in case somebody
+        // is filtering on it.
+        const TARGET: &str = "tracing_actix_web::middleware";
+        let message = &self.message;
+        match level {
+            Level::TRACE => tracing::trace!(target: TARGET, "{}: {}", message, error),
+            Level::DEBUG => tracing::debug!(target: TARGET, "{}: {}", message, error),
+            Level::INFO => tracing::info!(target: TARGET, "{}: {}", message, error),
+            Level::WARN => tracing::warn!(target: TARGET, "{}: {}", message, error),
+            Level::ERROR => tracing::error!(target: TARGET, "{}: {}", message, error),
+        }
+    }
+}
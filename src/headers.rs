@@ -0,0 +1,73 @@
+use crate::RedactionPolicy;
+use actix_web::http::header::{HeaderMap, HeaderName};
+use std::fmt;
+use tracing::Span;
+
+/// Record the headers in `allowlist` that are present in `headers` on `field`.
+///
+/// `tracing` requires field names to be known when the span is created, therefore we can't
+/// record each header on its own `http.request.header.<name>` field: all captured headers are
+/// recorded together, as a map from header name to the list of its values.  
+/// Dedicated fields can be declared by a custom [`RootSpanBuilder`](crate::RootSpanBuilder) -
+/// see [`TracingLoggerBuilder::capture_request_header`](crate::TracingLoggerBuilder::capture_request_header).
+pub(crate) fn record_headers(
+    span: &Span,
+    field: &'static str,
+    headers: &HeaderMap,
+    allowlist: &[HeaderName],
+    redaction: &RedactionPolicy,
+) {
+    if let Some(headers) = render_headers(headers, allowlist, redaction) {
+        span.record(field, tracing::field::display(headers));
+    }
+}
+
+/// Render the headers in `allowlist` that are present in `headers` - `None` if there are none.
+pub(crate) fn render_headers(
+    headers: &HeaderMap,
+    allowlist: &[HeaderName],
+    redaction: &RedactionPolicy,
+) -> Option<String> {
+    allowlist
+        .iter()
+        .any(|name| headers.contains_key(name))
+        .then(|| {
+            format!(
+                "{:?}",
+                CapturedHeaders {
+                    headers,
+                    allowlist,
+                    redaction,
+                }
+            )
+        })
+}
+
+/// Renders as `{"accept-language": ["en-GB", "en"], "x-tenant-id": ["acme"]}`.
+struct CapturedHeaders<'a> {
+    headers: &'a HeaderMap,
+    allowlist: &'a [HeaderName],
+    redaction: &'a RedactionPolicy,
+}
+
+impl fmt::Debug for CapturedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for name in self.allowlist {
+            let mut values = self.headers.get_all(name).peekable();
+            if values.peek().is_none() {
+                continue;
+            }
+            let is_redacted = self.redaction.is_redacted_header(name);
+            // Header values are not guaranteed to be valid UTF-8.
+            let values: Vec<_> = values
+                .map(|value| match is_redacted {
+                    true => self.redaction.marker_str().into(),
+                    false => String::from_utf8_lossy(value.as_bytes()),
+                })
+                .collect();
+            map.entry(&name.as_str(), &values);
+        }
+        map.finish()
+    }
+}
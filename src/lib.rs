@@ -12,7 +12,7 @@
 //! ```toml
 //! [dependencies]
 //! # ...
-//! tracing-actix-web = "0.7"
+//! tracing-actix-web = "0.8"
 //! tracing = "0.1"
 //! actix-web = "4"
 //! ```
@@ -39,7 +39,12 @@
 //! - `opentelemetry_0_30`: same as above but using `opentelemetry` 0.30;
 //! - `opentelemetry_0_31`: same as above but using `opentelemetry` 0.31;
 //! - `emit_event_on_error`: emit a [`tracing`] event when request processing fails with an error (enabled by default).
+//!   It can be overridden at runtime using [`TracingLoggerBuilder::emit_event_on_error`], while
+//!   [`ErrorEventPolicy`] lets you customise the event - e.g. its level for each status code.
 //! - `uuid_v7`: use the UUID v7 implementation inside [`RequestId`] instead of UUID v4 (disabled by default).
+//!   It also provides `UuidV7Generator`, to pick UUID v7 at runtime using [`TracingLoggerBuilder::request_id_generator`].
+//! - `regex`: redact the portions of the request path matching a regular expression, using
+//!   `RedactionPolicy::redact_path` (enabled by default). `Regex` is re-exported.
 //!
 //! ## Quickstart
 //!
@@ -199,6 +204,63 @@
 //! let custom_middleware = TracingLogger::<CustomLevelRootSpanBuilder>::new();
 //! ```
 //!
+//! ## Runtime configuration
+//!
+//! Not all knobs require a custom [`RootSpanBuilder`]: [`TracingLogger::builder`] returns a
+//! [`TracingLoggerBuilder`], which you can use to configure the middleware at runtime - e.g.
+//! based on values read from your configuration files.
+//!
+//! ```rust
+//! use tracing_actix_web::{Level, PathPattern, SamplingPolicy, TracingLogger};
+//!
+//! let level = if cfg!(debug_assertions) { Level::DEBUG } else { Level::INFO };
+//! let custom_middleware = TracingLogger::builder()
+//!     .span_level(level)
+//!     .emit_event_on_error(true)
+//!     // Don't trace health checks
+//!     .exclude(PathPattern::exact("/health_check"))
+//!     // Only trace 10% of the remaining requests
+//!     .sampling(SamplingPolicy::ratio(0.1))
+//!     .build();
+//! ```
+//!
+//! If your root span builder itself needs some state - e.g. a lookup table populated at startup -
+//! implement [`StatefulRootSpanBuilder`] instead of [`RootSpanBuilder`]: it receives `&self`, and you
+//! can pass an instance of it to [`TracingLogger::with_builder`].
+//!
+//! ```rust
+//! use actix_web::dev::{ServiceResponse, ServiceRequest};
+//! use actix_web::Error;
+//! use std::collections::HashMap;
+//! use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder, StatefulRootSpanBuilder, TracingLogger};
+//! use tracing::Span;
+//!
+//! pub struct TenantRootSpanBuilder {
+//!     tenants: HashMap<String, String>,
+//! }
+//!
+//! impl StatefulRootSpanBuilder for TenantRootSpanBuilder {
+//!     fn on_request_start(&self, request: &ServiceRequest) -> Span {
+//!         let tenant = request
+//!             .headers()
+//!             .get("x-tenant-id")
+//!             .and_then(|h| h.to_str().ok())
+//!             .and_then(|id| self.tenants.get(id))
+//!             .map(String::as_str)
+//!             .unwrap_or("unknown");
+//!         tracing_actix_web::root_span!(request, tenant)
+//!     }
+//!
+//!     fn on_request_end(&self, span: Span, outcome: &Result<ServiceResponse<()>, Error>) {
+//!         DefaultRootSpanBuilder::on_request_end(span, outcome);
+//!     }
+//! }
+//!
+//! let custom_middleware = TracingLogger::with_builder(TenantRootSpanBuilder {
+//!     tenants: HashMap::new(),
+//! });
+//! ```
+//!
 //! ## The [`RootSpan`] extractor
 //!
 //! It often happens that not all information about a task is known upfront, encoded in the incoming request.  
@@ -268,6 +330,9 @@
 //!
 //! Optionally, using the `uuid_v7` feature flag will allow [`RequestId`] to use UUID v7 instead of the currently used UUID v4.
 //!
+//! If your edge proxy already assigns an identifier to each request (e.g. `x-request-id`), you can use
+//! [`TracingLoggerBuilder::inherit_request_id_from`] to reuse it instead of generating a new one.
+//!
 //! ## Trace Id
 //!
 //! To fulfill a request you often have to perform additional I/O operations - e.g. calls to other REST or gRPC APIs, database queries, etc.
@@ -286,15 +351,47 @@
 //!
 //! [root span]: crate::RootSpan
 //! [`actix-web`]: https://docs.rs/actix-web/4.0.0-beta.13/actix_web/index.html
+mod backtrace;
+mod client_address;
+mod config;
+mod error_chain;
+mod error_event;
+mod headers;
 mod middleware;
+mod path_pattern;
+mod promotion;
+mod redaction;
+mod request_body;
+mod request_fields;
 mod request_id;
 mod root_span;
 mod root_span_builder;
+mod sampling;
+mod semconv;
+mod status_classifier;
+mod traced_error;
 
+pub use client_address::{InvalidIpRange, IpRange};
+pub use config::TracingLoggerBuilder;
+pub use error_event::{ErrorEventPolicy, ErrorFormat};
 pub use middleware::{StreamSpan, TracingLogger};
-pub use request_id::RequestId;
+pub use path_pattern::PathPattern;
+pub use redaction::RedactionPolicy;
+#[cfg(feature = "uuid_v7")]
+pub use request_id::UuidV7Generator;
+pub use request_id::{RequestId, RequestIdGenerator, UuidV4Generator};
 pub use root_span::RootSpan;
-pub use root_span_builder::{DefaultRootSpanBuilder, RootSpanBuilder};
+pub use root_span_builder::{
+    BodyEnd, BodyOutcome, DefaultRootSpanBuilder, RootSpanBuilder, StatefulRootSpanBuilder,
+    StatelessRootSpanBuilder,
+};
+pub use sampling::SamplingPolicy;
+pub use semconv::SemanticConventions;
+pub use status_classifier::{StatusClass, StatusClassifier};
+pub use traced_error::TracedError;
+// Re-exporting `Regex` since it's used to configure `RedactionPolicy`
+#[cfg(feature = "regex")]
+pub use regex::Regex;
 // Re-exporting the `Level` enum since it's used in our `root_span!` macro
 pub use tracing::Level;
 
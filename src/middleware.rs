@@ -1,12 +1,25 @@
-use crate::{DefaultRootSpanBuilder, RequestId, RootSpan, RootSpanBuilder};
+use crate::config::{Config, RequestContext};
+use crate::promotion::{emit_promotion_event, RequestSummary};
+use crate::request_body::track_request_body;
+use crate::sampling::SamplingDecision;
+use crate::{
+    BodyEnd, BodyOutcome, DefaultRootSpanBuilder, RequestId, RootSpanBuilder,
+    StatefulRootSpanBuilder, TracingLoggerBuilder,
+};
 use actix_web::body::{BodySize, MessageBody};
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
 use actix_web::http::StatusCode;
 use actix_web::web::Bytes;
-use actix_web::{Error, HttpMessage, ResponseError};
+use actix_web::{Error, HttpMessage, HttpResponse, ResponseError};
+use std::any::Any;
 use std::future::{ready, Future, Ready};
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tracing::Span;
 
 /// `TracingLogger` is a middleware to capture structured diagnostic when processing an HTTP request.
@@ -75,17 +88,31 @@ use tracing::Span;
 ///     );
 /// ```
 ///
+/// # Runtime configuration
+///
+/// Use [`TracingLogger::builder`] to tune the behaviour of `TracingLogger` at runtime - e.g.
+/// using values read from your configuration files.
+///
+/// ```rust
+/// use actix_web::App;
+/// use tracing_actix_web::{Level, TracingLogger};
+///
+/// let app = App::new()
+///     .wrap(TracingLogger::builder().span_level(Level::DEBUG).build());
+/// ```
+///
 /// [`actix-web`]: https://docs.rs/actix-web
 /// [`Logger`]: https://docs.rs/actix-web/4.0.0-beta.13/actix_web/middleware/struct.Logger.html
 /// [`Compat`]: https://docs.rs/actix-web/4.0.0-beta.13/actix_web/middleware/struct.Compat.html
 /// [`tracing`]: https://docs.rs/tracing
-pub struct TracingLogger<RootSpan: RootSpanBuilder> {
+pub struct TracingLogger<RootSpan: ?Sized> {
+    config: Arc<Config>,
     root_span_builder: std::marker::PhantomData<RootSpan>,
 }
 
-impl<RootSpan: RootSpanBuilder> Clone for TracingLogger<RootSpan> {
+impl<RootSpan: ?Sized> Clone for TracingLogger<RootSpan> {
     fn clone(&self) -> Self {
-        Self::new()
+        Self::from_config(self.config.clone())
     }
 }
 
@@ -95,9 +122,36 @@ impl Default for TracingLogger<DefaultRootSpanBuilder> {
     }
 }
 
-impl<RootSpan: RootSpanBuilder> TracingLogger<RootSpan> {
+impl TracingLogger<DefaultRootSpanBuilder> {
+    /// Configure a `TracingLogger` at runtime - check out [`TracingLoggerBuilder`] for
+    /// the available options.
+    ///
+    /// Use [`TracingLoggerBuilder::new`] if you are working with a custom [`RootSpanBuilder`].
+    pub fn builder() -> TracingLoggerBuilder<DefaultRootSpanBuilder> {
+        TracingLoggerBuilder::new()
+    }
+}
+
+impl TracingLogger<dyn StatefulRootSpanBuilder> {
+    /// Build the root span using an instance of a [`StatefulRootSpanBuilder`] - e.g. a builder
+    /// configured at startup using values read from your configuration files.
+    pub fn with_builder(root_span_builder: impl StatefulRootSpanBuilder) -> Self {
+        TracingLogger::builder()
+            .root_span_builder(root_span_builder)
+            .build()
+    }
+}
+
+impl<RootSpan: RootSpanBuilder + 'static> TracingLogger<RootSpan> {
     pub fn new() -> TracingLogger<RootSpan> {
+        TracingLoggerBuilder::new().build()
+    }
+}
+
+impl<RootSpan: ?Sized> TracingLogger<RootSpan> {
+    pub(crate) fn from_config(config: Arc<Config>) -> TracingLogger<RootSpan> {
         TracingLogger {
+            config,
             root_span_builder: Default::default(),
         }
     }
@@ -108,7 +162,7 @@ where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
     B: MessageBody + 'static,
-    RootSpan: RootSpanBuilder,
+    RootSpan: RequestEnd + ?Sized,
 {
     type Response = ServiceResponse<StreamSpan<B>>;
     type Error = Error;
@@ -119,70 +173,148 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(TracingLoggerMiddleware {
             service,
-            root_span_builder: std::marker::PhantomData,
+            config: self.config.clone(),
+            root_span_builder: PhantomData,
         }))
     }
 }
 
 #[doc(hidden)]
-pub struct TracingLoggerMiddleware<S, RootSpanBuilder> {
+pub struct TracingLoggerMiddleware<S, RootSpan: ?Sized> {
     service: S,
-    root_span_builder: std::marker::PhantomData<RootSpanBuilder>,
+    config: Arc<Config>,
+    root_span_builder: PhantomData<RootSpan>,
 }
 
 #[allow(clippy::type_complexity)]
-impl<S, B, RootSpanType> Service<ServiceRequest> for TracingLoggerMiddleware<S, RootSpanType>
+impl<S, B, RootSpan> Service<ServiceRequest> for TracingLoggerMiddleware<S, RootSpan>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
     B: MessageBody + 'static,
-    RootSpanType: RootSpanBuilder,
+    RootSpan: RequestEnd + ?Sized,
 {
     type Response = ServiceResponse<StreamSpan<B>>;
     type Error = Error;
-    type Future = TracingResponse<S::Future, RootSpanType>;
+    type Future = TracingResponse<S::Future, RootSpan>;
 
     actix_web::dev::forward_ready!(service);
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        req.extensions_mut().insert(RequestId::generate());
-        let root_span = RootSpanType::on_request_start(&req);
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let started_at = Instant::now();
+        let request_id = self.config.request_id(&req);
+        req.extensions_mut().insert(request_id.clone());
+        let context = RequestContext::new(self.config.clone(), &req);
+        req.extensions_mut().insert(context.clone());
+        let is_excluded = self.config.is_excluded(&req);
+        let sampling_decision = match &self.config.sampling {
+            Some(sampling) if !is_excluded => Some(sampling.sample(&req)),
+            _ => None,
+        };
+        let is_traced = !is_excluded && sampling_decision.is_none_or(SamplingDecision::is_sampled);
+        let root_span = if is_traced {
+            let root_span = self.config.root_span_builder.on_request_start(&req);
+            if let Some(decision) = sampling_decision {
+                root_span.record("sampling.decision", decision.as_str());
+            }
+            if self.config.capture_request_body_metadata {
+                track_request_body(&mut req, &root_span);
+            }
+            root_span
+        } else {
+            Span::none()
+        };
 
-        let root_span_wrapper = RootSpan::new(root_span.clone());
+        let root_span_wrapper = crate::RootSpan::new(root_span.clone());
         req.extensions_mut().insert(root_span_wrapper);
 
+        let summary = if !is_excluded && self.config.is_promotion_enabled() {
+            Some(RequestSummary::new(&req, &context, sampling_decision))
+        } else {
+            None
+        };
+
         let fut = root_span.in_scope(|| self.service.call(req));
 
         TracingResponse {
             fut,
             span: root_span,
-            _root_span_type: std::marker::PhantomData,
+            context,
+            request_id,
+            is_excluded,
+            is_traced,
+            summary,
+            started_at,
+            root_span_builder: PhantomData,
         }
     }
 }
 
 #[doc(hidden)]
 #[pin_project::pin_project]
-pub struct TracingResponse<F, RootSpanType> {
+pub struct TracingResponse<F, RootSpan: ?Sized> {
     #[pin]
     fut: F,
     span: Span,
-    _root_span_type: std::marker::PhantomData<RootSpanType>,
+    context: RequestContext,
+    request_id: RequestId,
+    is_excluded: bool,
+    is_traced: bool,
+    /// `None` if the request can't be promoted.
+    summary: Option<RequestSummary>,
+    started_at: Instant,
+    root_span_builder: PhantomData<RootSpan>,
 }
 
 #[doc(hidden)]
-#[pin_project::pin_project]
+#[pin_project::pin_project(PinnedDrop)]
 pub struct StreamSpan<B> {
     #[pin]
     body: B,
+    tracker: BodyTracker,
+}
+
+impl<B: MessageBody> StreamSpan<B> {
+    fn new(body: B, tracker: BodyTracker) -> Self {
+        Self { body, tracker }
+    }
+}
+
+/// Keeps track of the progress of a response body, in order to record how it ended.
+struct BodyTracker {
     span: Span,
+    config: Arc<Config>,
+    is_traced: bool,
+    started_at: Instant,
+    /// The size of the body, as advertised when the response head was sent.
+    size: BodySize,
+    bytes_sent: u64,
+    is_finished: bool,
+}
+
+impl BodyTracker {
+    fn finish(&mut self, outcome: BodyOutcome) {
+        self.is_finished = true;
+        let span = &self.span;
+        span.record("http.response.body.size", self.bytes_sent);
+        span.record("http.response.body.outcome", outcome.as_str());
+        if self.is_traced {
+            let body_end = BodyEnd::new(outcome, self.bytes_sent, self.started_at.elapsed());
+            let config = &self.config;
+            span.in_scope(|| {
+                config
+                    .root_span_builder
+                    .on_body_end(span.clone(), &body_end)
+            });
+        }
+    }
 }
 
-impl<F, B, RootSpanType> Future for TracingResponse<F, RootSpanType>
+impl<F, B, RootSpan> Future for TracingResponse<F, RootSpan>
 where
     F: Future<Output = Result<ServiceResponse<B>, Error>>,
     B: MessageBody + 'static,
-    RootSpanType: RootSpanBuilder,
+    RootSpan: RequestEnd + ?Sized,
 {
     type Output = Result<ServiceResponse<StreamSpan<B>>, Error>;
 
@@ -191,21 +323,65 @@ where
 
         let fut = this.fut;
         let span = this.span;
+        let context = this.context;
+        let config = &context.config;
+        let request_id = this.request_id;
+        let is_excluded = *this.is_excluded;
+        let is_traced = *this.is_traced;
+        let summary = this.summary;
+        let started_at = *this.started_at;
 
-        span.in_scope(|| match fut.poll(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(outcome) => {
-                RootSpanType::on_request_end(Span::current(), &outcome);
+        span.in_scope(|| match catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Err(panic) => {
+                if !is_excluded {
+                    record_panic(span, config, started_at.elapsed(), &*panic);
+                }
+                // Let actix-web deal with the panic, as it would without the middleware.
+                resume_unwind(panic)
+            }
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(outcome)) => {
+                // Time to response head - the body may take longer to be streamed back.
+                let elapsed = started_at.elapsed();
+                let outcome = if is_traced {
+                    span.record("http.server.duration_ms", elapsed.as_secs_f64() * 1000.0);
+                    context.scope(|| {
+                        RootSpan::on_request_end(
+                            &*config.root_span_builder,
+                            Span::current(),
+                            outcome,
+                        )
+                    })
+                } else {
+                    outcome
+                };
+
+                if let Some(summary) = summary {
+                    emit_promotion_event(summary, &outcome, elapsed, context);
+                }
 
-                #[cfg(feature = "emit_event_on_error")]
-                {
-                    emit_event_on_error(&outcome);
+                // Requests that were not sampled still get the error event.
+                if !is_excluded {
+                    emit_event_on_error(context, &outcome);
                 }
 
+                let outcome = match &config.request_id_response_header {
+                    Some(header_name) => set_request_id_header(outcome, header_name, request_id),
+                    None => outcome,
+                };
+
                 Poll::Ready(outcome.map(|service_response| {
-                    service_response.map_body(|_, body| StreamSpan {
-                        body,
-                        span: span.clone(),
+                    service_response.map_body(|_, body| {
+                        let tracker = BodyTracker {
+                            span: span.clone(),
+                            config: config.clone(),
+                            is_traced,
+                            started_at,
+                            size: body.size(),
+                            bytes_sent: 0,
+                            is_finished: false,
+                        };
+                        StreamSpan::new(body, tracker)
                     })
                 }))
             }
@@ -213,9 +389,121 @@ where
     }
 }
 
+/// How [`TracingLogger`] hands the outcome of a request over to its root span builder.
+///
+/// It is implemented for every [`RootSpanBuilder`], which gets the response with its actual
+/// body type, and for `dyn StatefulRootSpanBuilder`.
+#[doc(hidden)]
+pub trait RequestEnd {
+    /// `root_span_builder` is the builder stored in the configuration of the middleware.
+    fn on_request_end<B: MessageBody>(
+        root_span_builder: &dyn StatefulRootSpanBuilder,
+        span: Span,
+        outcome: Result<ServiceResponse<B>, Error>,
+    ) -> Result<ServiceResponse<B>, Error>;
+}
+
+impl<RootSpan: RootSpanBuilder> RequestEnd for RootSpan {
+    fn on_request_end<B: MessageBody>(
+        _root_span_builder: &dyn StatefulRootSpanBuilder,
+        span: Span,
+        outcome: Result<ServiceResponse<B>, Error>,
+    ) -> Result<ServiceResponse<B>, Error> {
+        RootSpan::on_request_end(span, &outcome);
+        outcome
+    }
+}
+
+impl RequestEnd for dyn StatefulRootSpanBuilder {
+    /// [`StatefulRootSpanBuilder`] is object-safe, therefore its `on_request_end` cannot be
+    /// generic over the body type: we detach the body while the builder inspects the outcome
+    /// and re-attach it afterwards.
+    fn on_request_end<B: MessageBody>(
+        root_span_builder: &dyn StatefulRootSpanBuilder,
+        span: Span,
+        outcome: Result<ServiceResponse<B>, Error>,
+    ) -> Result<ServiceResponse<B>, Error> {
+        let mut body = None;
+        let outcome = outcome.map(|response| response.map_body(|_, b| body = Some(b)));
+        root_span_builder.on_request_end(span, &outcome);
+        outcome.map(|response| {
+            response.map_body(|_, ()| body.expect("The body was detached from this very response"))
+        })
+    }
+}
+
+fn set_request_id_header<B>(
+    outcome: Result<ServiceResponse<B>, Error>,
+    header_name: &HeaderName,
+    request_id: &RequestId,
+) -> Result<ServiceResponse<B>, Error> {
+    let header_value = match HeaderValue::from_str(&request_id.to_string()) {
+        Ok(header_value) => header_value,
+        Err(_) => return outcome,
+    };
+    match outcome {
+        Ok(mut response) => {
+            if !response.headers().contains_key(header_name) {
+                response
+                    .headers_mut()
+                    .insert(header_name.clone(), header_value);
+            }
+            Ok(response)
+        }
+        Err(error) => Err(WithResponseHeader {
+            error,
+            header_name: header_name.clone(),
+            header_value,
+        }
+        .into()),
+    }
+}
+
+/// A wrapper around an [`Error`] that attaches an additional header to the error response
+/// built by `actix-web` for it.
+///
+/// We can't build the error response ourselves for an `Err` outcome: the [`HttpRequest`] it
+/// requires has already been consumed by the wrapped service.
+///
+/// [`HttpRequest`]: actix_web::HttpRequest
+struct WithResponseHeader {
+    error: Error,
+    header_name: HeaderName,
+    header_value: HeaderValue,
+}
+
+impl ResponseError for WithResponseHeader {
+    fn status_code(&self) -> StatusCode {
+        self.error.as_response_error().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = self.error.error_response();
+        if !response.headers().contains_key(&self.header_name) {
+            response
+                .headers_mut()
+                .insert(self.header_name.clone(), self.header_value.clone());
+        }
+        response
+    }
+}
+
+impl std::fmt::Display for WithResponseHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::fmt::Debug for WithResponseHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.error, f)
+    }
+}
+
 impl<B> MessageBody for StreamSpan<B>
 where
     B: MessageBody,
+    B::Error: 'static,
 {
     type Error = B::Error;
 
@@ -230,31 +518,107 @@ where
         let this = self.project();
 
         let body = this.body;
-        let span = this.span;
-        span.in_scope(|| body.poll_next(cx))
+        let tracker = this.tracker;
+        if tracker.is_finished {
+            return Poll::Ready(None);
+        }
+        match tracker.span.in_scope(|| body.poll_next(cx)) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(Ok(chunk))) => {
+                tracker.bytes_sent += chunk.len() as u64;
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(error))) => {
+                if let Some(error) = render_body_error(&error) {
+                    tracker
+                        .span
+                        .record("http.response.body.error", tracing::field::display(error));
+                }
+                tracker.finish(BodyOutcome::Failed);
+                Poll::Ready(Some(Err(error)))
+            }
+            Poll::Ready(None) => {
+                tracker.finish(BodyOutcome::Completed);
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+/// Render the error of a failed response body, if its type is one we know how to display.
+///
+/// The body type is generic, its error is only required to be convertible into a boxed error:
+/// we cannot render it without giving it up. Most applications box their response bodies though
+/// (`BoxBody` or `EitherBody`), whose errors are covered here.
+fn render_body_error<E: 'static>(error: &E) -> Option<String> {
+    let error = error as &dyn Any;
+    if let Some(error) = error.downcast_ref::<Box<dyn std::error::Error>>() {
+        Some(error.to_string())
+    } else if let Some(error) = error.downcast_ref::<Error>() {
+        Some(error.to_string())
+    } else {
+        error
+            .downcast_ref::<std::io::Error>()
+            .map(|error| error.to_string())
     }
 }
 
-fn emit_event_on_error<B: 'static>(outcome: &Result<ServiceResponse<B>, actix_web::Error>) {
+#[pin_project::pinned_drop]
+impl<B> PinnedDrop for StreamSpan<B> {
+    fn drop(self: Pin<&mut Self>) {
+        let tracker = self.project().tracker;
+        if tracker.is_finished {
+            return;
+        }
+        // `actix-web` does not necessarily poll a body until its end - e.g. it won't poll an
+        // empty one at all - therefore we check if everything that was advertised has been sent.
+        let is_completed = match tracker.size {
+            BodySize::None => true,
+            BodySize::Sized(size) => size == tracker.bytes_sent,
+            BodySize::Stream => false,
+        };
+        tracker.finish(if is_completed {
+            BodyOutcome::Completed
+        } else {
+            BodyOutcome::Aborted
+        });
+    }
+}
+
+/// Record a panic raised while processing a request on its root span, then emit the error event.
+fn record_panic(span: &Span, config: &Config, elapsed: Duration, panic: &(dyn Any + Send)) {
+    let message = if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Box<dyn Any>"
+    };
+    span.record("http.server.duration_ms", elapsed.as_secs_f64() * 1000.0);
+    span.record("exception.message", message);
+    span.record("otel.status_code", "ERROR");
+    config.error_event_policy.emit_panic(message);
+}
+
+fn emit_event_on_error<B: 'static>(
+    context: &RequestContext,
+    outcome: &Result<ServiceResponse<B>, actix_web::Error>,
+) {
+    let policy = &context.config.error_event_policy;
     match outcome {
         Ok(response) => {
             if let Some(err) = response.response().error() {
                 // use the status code already constructed for the outgoing HTTP response
-                emit_error_event(err.as_response_error(), response.status())
+                let status_code = response.status();
+                let class = context.status_class(status_code);
+                policy.emit(err.as_response_error(), status_code, class)
             }
         }
         Err(error) => {
             let response_error = error.as_response_error();
-            emit_error_event(response_error, response_error.status_code())
+            let status_code = response_error.status_code();
+            let class = context.status_class(status_code);
+            policy.emit(response_error, status_code, class)
         }
     }
 }
-
-fn emit_error_event(response_error: &dyn ResponseError, status_code: StatusCode) {
-    let error_msg_prefix = "Error encountered while processing the incoming HTTP request";
-    if status_code.is_client_error() {
-        tracing::warn!("{}: {:?}", error_msg_prefix, response_error);
-    } else {
-        tracing::error!("{}: {:?}", error_msg_prefix, response_error);
-    }
-}
@@ -0,0 +1,197 @@
+use actix_web::HttpRequest;
+
+/// A pattern to select a subset of the requests processed by [`TracingLogger`] - e.g. to
+/// exclude health checks from tracing via [`TracingLoggerBuilder::exclude`].
+///
+/// A pattern is matched against both the route template of the request (e.g. `/users/{id}`,
+/// see [`HttpRequest::match_pattern`]) and its raw path (e.g. `/users/42`): the request
+/// is selected if either of them matches.
+///
+/// Three kinds of patterns are supported:
+///
+/// - [`PathPattern::exact`], e.g. `/health`;
+/// - [`PathPattern::prefix`], e.g. `/internal/`;
+/// - [`PathPattern::glob`], e.g. `/health/*` or `/static/**`.
+///
+/// # Usage
+///
+/// ```rust
+/// use tracing_actix_web::PathPattern;
+///
+/// assert!(PathPattern::exact("/health").matches("/health"));
+/// assert!(!PathPattern::exact("/health").matches("/health/live"));
+///
+/// assert!(PathPattern::prefix("/internal/").matches("/internal/metrics"));
+///
+/// assert!(PathPattern::glob("/health/*").matches("/health/live"));
+/// assert!(!PathPattern::glob("/health/*").matches("/health/live/details"));
+/// assert!(PathPattern::glob("/static/**").matches("/static/css/main.css"));
+/// assert!(PathPattern::glob("/users/{id}/*").matches("/users/{id}/avatar"));
+/// ```
+///
+/// [`TracingLogger`]: crate::TracingLogger
+/// [`TracingLoggerBuilder::exclude`]: crate::TracingLoggerBuilder::exclude
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathPattern(Kind);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Exact(String),
+    Prefix(String),
+    Glob(String),
+}
+
+impl PathPattern {
+    /// Match paths (or route templates) equal to `path`.
+    pub fn exact(path: impl Into<String>) -> Self {
+        Self(Kind::Exact(path.into()))
+    }
+
+    /// Match paths (or route templates) starting with `prefix`.
+    pub fn prefix(prefix: impl Into<String>) -> Self {
+        Self(Kind::Prefix(prefix.into()))
+    }
+
+    /// Match paths (or route templates) against a glob pattern:
+    ///
+    /// - `?` matches any single character, except `/`;
+    /// - `*` matches any sequence of characters, except `/`;
+    /// - `**` matches any sequence of characters, including `/`.
+    ///
+    /// Any other character matches itself.
+    pub fn glob(pattern: impl Into<String>) -> Self {
+        Self(Kind::Glob(pattern.into()))
+    }
+
+    /// `true` if `path` matches the pattern.
+    pub fn matches(&self, path: &str) -> bool {
+        match &self.0 {
+            Kind::Exact(exact) => path == exact,
+            Kind::Prefix(prefix) => path.starts_with(prefix.as_str()),
+            Kind::Glob(glob) => glob_matches(glob.as_bytes(), path.as_bytes()),
+        }
+    }
+
+    /// `true` if either the route template or the path of `request` match the pattern.
+    pub(crate) fn matches_request(&self, request: &HttpRequest) -> bool {
+        request
+            .match_pattern()
+            .is_some_and(|route| self.matches(&route))
+            || self.matches(request.path())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Byte(u8),
+    /// `?`
+    AnyByte,
+    /// `*`
+    Segment,
+    /// `**`
+    Any,
+}
+
+fn tokenize(mut pattern: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::with_capacity(pattern.len());
+    loop {
+        let (token, rest) = match pattern {
+            [] => return tokens,
+            [b'*', b'*', rest @ ..] => (Token::Any, rest),
+            [b'*', rest @ ..] => (Token::Segment, rest),
+            [b'?', rest @ ..] => (Token::AnyByte, rest),
+            [c, rest @ ..] => (Token::Byte(*c), rest),
+        };
+        tokens.push(token);
+        pattern = rest;
+    }
+}
+
+/// Match `path` against a glob `pattern`, tracking every position of the pattern that can be
+/// reached after each byte of the path.
+///
+/// The path is client-controlled: backtracking would be exponential in the number of
+/// wildcards, while this takes `O(pattern.len() * path.len())` time.
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    let tokens = tokenize(pattern);
+    // `states[i]` is `true` if the first `i` tokens match the path consumed so far.
+    let mut states = vec![false; tokens.len() + 1];
+    let mut next = states.clone();
+    states[0] = true;
+    skip_empty_wildcards(&tokens, &mut states);
+    for &c in path {
+        next.iter_mut().for_each(|state| *state = false);
+        for (i, token) in tokens.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+            match *token {
+                Token::Byte(b) if b == c => next[i + 1] = true,
+                Token::AnyByte if c != b'/' => next[i + 1] = true,
+                Token::Segment if c != b'/' => next[i] = true,
+                Token::Any => next[i] = true,
+                _ => {}
+            }
+        }
+        skip_empty_wildcards(&tokens, &mut next);
+        if !next.contains(&true) {
+            return false;
+        }
+        std::mem::swap(&mut states, &mut next);
+    }
+    states[tokens.len()]
+}
+
+/// Wildcards can match an empty sequence: the position after them is reachable as well.
+fn skip_empty_wildcards(tokens: &[Token], states: &mut [bool]) {
+    for (i, token) in tokens.iter().enumerate() {
+        if states[i] && matches!(token, Token::Segment | Token::Any) {
+            states[i + 1] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathPattern;
+
+    #[test]
+    fn double_star_matches_across_segments() {
+        let pattern = PathPattern::glob("/static/**");
+        assert!(pattern.matches("/static/"));
+        assert!(pattern.matches("/static/css/main.css"));
+        assert!(!pattern.matches("/assets/main.css"));
+        let pattern = PathPattern::glob("/**/health");
+        assert!(pattern.matches("/api/health"));
+        assert!(pattern.matches("/api/v1/health"));
+        assert!(!pattern.matches("/api/v1/health/live"));
+    }
+
+    #[test]
+    fn star_matches_within_a_segment() {
+        let pattern = PathPattern::glob("/users/*/avatar");
+        assert!(pattern.matches("/users/42/avatar"));
+        assert!(pattern.matches("/users//avatar"));
+        assert!(!pattern.matches("/users/42/43/avatar"));
+        let pattern = PathPattern::glob("/files/*.css");
+        assert!(pattern.matches("/files/main.css"));
+        assert!(!pattern.matches("/files/css/main.css"));
+    }
+
+    #[test]
+    fn question_mark_matches_a_single_character() {
+        let pattern = PathPattern::glob("/v?/health");
+        assert!(pattern.matches("/v1/health"));
+        assert!(!pattern.matches("/v/health"));
+        assert!(!pattern.matches("/v12/health"));
+        assert!(!pattern.matches("/v//health"));
+    }
+
+    #[test]
+    fn many_wildcards_do_not_backtrack() {
+        let pattern = PathPattern::glob("/**/**/**/**/**/**/**/**/health");
+        let path = format!("/{}", "a/".repeat(2000));
+        assert!(!pattern.matches(&path));
+        assert!(pattern.matches(&format!("{path}health")));
+    }
+}
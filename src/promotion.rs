@@ -0,0 +1,113 @@
+use crate::config::RequestContext;
+use crate::root_span_macro::private::{
+    display_if, http_flavor, network_protocol_version, RequestFields,
+};
+use crate::sampling::SamplingDecision;
+use crate::StatusClass;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A snapshot of the fields recorded on the root span, captured when the request comes in.
+///
+/// We can't hold on to the request itself while the inner service is processing it.
+pub(crate) struct RequestSummary {
+    fields: Arc<RequestFields>,
+    sampling_decision: Option<SamplingDecision>,
+}
+
+impl RequestSummary {
+    pub(crate) fn new(
+        request: &ServiceRequest,
+        context: &RequestContext,
+        sampling_decision: Option<SamplingDecision>,
+    ) -> Self {
+        Self {
+            fields: RequestFields::of(request, context),
+            sampling_decision,
+        }
+    }
+}
+
+/// Emit a `WARN` summary event if the request failed (according to the configured
+/// [`StatusClassifier`](crate::StatusClassifier)), an `INFO` one if it took longer than the
+/// configured threshold.
+///
+/// The event carries the fields that [`root_span!`] records when the request comes in, as
+/// well as the ones that describe its outcome - see
+/// [`TracingLoggerBuilder::promote_failed_requests`] for the full list.
+///
+/// [`root_span!`]: crate::root_span!
+/// [`TracingLoggerBuilder::promote_failed_requests`]: crate::TracingLoggerBuilder::promote_failed_requests
+pub(crate) fn emit_promotion_event<B>(
+    summary: &RequestSummary,
+    outcome: &Result<ServiceResponse<B>, Error>,
+    elapsed: Duration,
+    context: &RequestContext,
+) {
+    let config = &context.config;
+    let (status_code, error) = match outcome {
+        Ok(response) => (response.status(), response.response().error()),
+        Err(error) => (error.as_response_error().status_code(), Some(error)),
+    };
+    let class = context.status_class(status_code);
+    let is_failed = config.promote_failed_requests && class == StatusClass::Failure;
+    let is_slow = config
+        .promote_slow_requests
+        .is_some_and(|threshold| elapsed > threshold);
+    // Pre-formatting errors is a workaround for https://github.com/tokio-rs/tracing/issues/1565
+    let exception_message = error.map(|error| error.to_string());
+    let exception_details = error.map(|error| format!("{error:?}"));
+    let otel_status_code = match class {
+        StatusClass::Success | StatusClass::ClientError => "OK",
+        StatusClass::Failure => "ERROR",
+    };
+    let fields = &summary.fields;
+    let url_query = fields.url_query();
+
+    macro_rules! promotion_event {
+        ($level:expr, $message:literal) => {
+            tracing::event!(
+                $level,
+                http.method = display_if(fields.legacy, &fields.method),
+                http.request.method = display_if(fields.stable, &fields.method),
+                http.route = %fields.route,
+                http.flavor = display_if(fields.legacy, http_flavor(fields.version)),
+                network.protocol.version = display_if(fields.stable, network_protocol_version(fields.version)),
+                http.scheme = display_if(fields.legacy, &fields.scheme),
+                url.scheme = display_if(fields.stable, &fields.scheme),
+                http.host = display_if(fields.legacy, &fields.host),
+                server.address = %fields.server_address,
+                server.port = fields.server_port,
+                network.peer.address = fields.peer_addr.map(|addr| tracing::field::display(addr.ip())),
+                network.peer.port = fields.peer_addr.map(|addr| addr.port()),
+                http.client_ip = display_if(fields.legacy, &fields.client_address),
+                client.address = display_if(fields.stable, &fields.client_address),
+                client.forwarded_header_ignored = fields.is_forwarded_header_ignored.then_some(true),
+                http.user_agent = display_if(fields.legacy, &fields.user_agent),
+                user_agent.original = display_if(fields.stable, &fields.user_agent),
+                http.target = display_if(fields.legacy, &fields.target),
+                url.path = display_if(fields.stable, fields.url_path()),
+                url.query = display_if(fields.stable && url_query.is_some(), url_query.unwrap_or("")),
+                http.request.headers = fields.request_headers.as_deref().map(tracing::field::display),
+                http.status_code = fields.legacy.then_some(status_code.as_u16()),
+                http.response.status_code = fields.stable.then_some(status_code.as_u16()),
+                http.server.duration_ms = elapsed.as_secs_f64() * 1000.0,
+                otel.status_code = otel_status_code,
+                request_id = %fields.request_id,
+                request_id.inherited = fields.request_id.is_inherited(),
+                sampling.decision = summary.sampling_decision.map(SamplingDecision::as_str),
+                exception.message = exception_message.as_deref(),
+                exception.details = exception_details.as_deref(),
+                $message
+            )
+        };
+    }
+
+    if is_failed {
+        promotion_event!(tracing::Level::WARN, "HTTP request failed");
+    } else if is_slow {
+        promotion_event!(tracing::Level::INFO, "Slow HTTP request");
+    }
+}
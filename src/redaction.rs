@@ -0,0 +1,323 @@
+use actix_web::http::header::{self, HeaderName};
+#[cfg(feature = "regex")]
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// `RedactionPolicy` scrubs sensitive data - e.g. API keys passed as query parameters or
+/// authorization headers - before it is recorded on the root span.
+///
+/// It applies to:
+///
+/// - the request target (`http.target`), recorded by [`root_span!`]: the values of the
+///   specified query parameters are replaced by the marker, as well as the portions of the
+///   path matching the specified patterns;
+/// - the headers captured via [`TracingLoggerBuilder::capture_request_header`] and
+///   [`TracingLoggerBuilder::capture_response_header`]: the values of the specified headers
+///   are replaced by the marker.
+///
+/// `authorization`, `proxy-authorization`, `cookie` and `set-cookie` headers are always
+/// redacted. The default marker is `[REDACTED]`.
+///
+/// # Usage
+///
+/// ```rust
+/// use actix_web::http::header::HeaderName;
+/// use tracing_actix_web::{RedactionPolicy, Regex, TracingLogger};
+///
+/// let redaction = RedactionPolicy::new()
+///     .redact_query_param("api_key")
+///     .redact_query_param("code")
+///     .redact_header(HeaderName::from_static("x-api-key"))
+///     // Only the capture group is redacted, e.g. `/reset/[REDACTED]/confirm`
+///     .redact_path(Regex::new("^/reset/([^/]+)").unwrap())
+///     .marker("***");
+///
+/// assert_eq!(
+///     redaction.redact_target("/reset/s3cr3t/confirm?api_key=abc&lang=en"),
+///     "/reset/***/confirm?api_key=***&lang=en"
+/// );
+///
+/// let logger = TracingLogger::builder()
+///     .redaction(redaction)
+///     .build();
+/// ```
+///
+/// [`root_span!`]: crate::root_span!
+/// [`TracingLoggerBuilder::capture_request_header`]: crate::TracingLoggerBuilder::capture_request_header
+/// [`TracingLoggerBuilder::capture_response_header`]: crate::TracingLoggerBuilder::capture_response_header
+#[derive(Clone)]
+pub struct RedactionPolicy {
+    query_params: Vec<String>,
+    headers: Vec<HeaderName>,
+    path_redactors: Vec<PathRedactor>,
+    marker: String,
+}
+
+/// Returns the ranges of the path to be replaced by the marker.
+type PathRedactor = Arc<dyn Fn(&str) -> Vec<Range<usize>> + Send + Sync>;
+
+impl fmt::Debug for RedactionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedactionPolicy")
+            .field("query_params", &self.query_params)
+            .field("headers", &self.headers)
+            .field("path_redactors", &self.path_redactors.len())
+            .field("marker", &self.marker)
+            .finish()
+    }
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RedactionPolicy {
+    pub fn new() -> Self {
+        Self {
+            query_params: Vec::new(),
+            headers: vec![
+                header::AUTHORIZATION,
+                header::PROXY_AUTHORIZATION,
+                header::COOKIE,
+                header::SET_COOKIE,
+            ],
+            path_redactors: Vec::new(),
+            marker: "[REDACTED]".into(),
+        }
+    }
+
+    /// Redact the value of the query parameter named `name`.
+    ///
+    /// Parameter names are percent-decoded before being compared to `name`: `api%5Fkey` is
+    /// redacted as well as `api_key`.
+    pub fn redact_query_param(mut self, name: impl Into<String>) -> Self {
+        self.query_params.push(name.into());
+        self
+    }
+
+    /// Redact the values of the header named `header_name`, if captured.
+    pub fn redact_header(mut self, header_name: HeaderName) -> Self {
+        self.headers.push(header_name);
+        self
+    }
+
+    /// Redact the portions of the request path matching `pattern`.
+    ///
+    /// If the pattern has capture groups, only the captured portions are replaced by the
+    /// marker; otherwise the whole match is.
+    ///
+    /// It requires the `regex` feature flag, enabled by default.
+    #[cfg(feature = "regex")]
+    pub fn redact_path(self, pattern: Regex) -> Self {
+        self.redact_path_with(move |path| {
+            pattern
+                .captures_iter(path)
+                .flat_map(|captures| {
+                    let skip = if captures.len() > 1 { 1 } else { 0 };
+                    captures
+                        .iter()
+                        .skip(skip)
+                        .flatten()
+                        .map(|group| group.range())
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        })
+    }
+
+    /// Redact the portions of the request path returned by `redactor`, as byte ranges: each
+    /// of them is replaced by the marker.
+    ///
+    /// Ranges that overlap with a previous one, are empty, are out of bounds or do not fall on
+    /// character boundaries are ignored.
+    /// If more than one path redactor is specified, they are applied in order, each to the
+    /// output of the previous one.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::RedactionPolicy;
+    ///
+    /// // Hide the token in `/reset/{token}/confirm`
+    /// let redaction = RedactionPolicy::new().redact_path_with(|path| {
+    ///     let start = "/reset/".len();
+    ///     match path.strip_prefix("/reset/").and_then(|rest| rest.find('/')) {
+    ///         Some(len) => vec![start..start + len],
+    ///         None => vec![],
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(redaction.redact_target("/reset/s3cr3t/confirm"), "/reset/[REDACTED]/confirm");
+    /// ```
+    pub fn redact_path_with<F>(mut self, redactor: F) -> Self
+    where
+        F: Fn(&str) -> Vec<Range<usize>> + Send + Sync + 'static,
+    {
+        self.path_redactors.push(Arc::new(redactor));
+        self
+    }
+
+    /// The string that replaces redacted values. It defaults to `[REDACTED]`.
+    pub fn marker(mut self, marker: impl Into<String>) -> Self {
+        self.marker = marker.into();
+        self
+    }
+
+    /// Redact a request target, i.e. a path with an optional query string.
+    pub fn redact_target<'a>(&self, target: &'a str) -> Cow<'a, str> {
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        let path = self.redact_path_ranges(path);
+        let query = query.map(|query| self.redact_query(query));
+        match query {
+            None => path,
+            Some(Cow::Borrowed(_)) if matches!(path, Cow::Borrowed(_)) => Cow::Borrowed(target),
+            Some(query) => format!("{path}?{query}").into(),
+        }
+    }
+
+    /// `true` if the values of the header named `header_name` must be redacted.
+    pub(crate) fn is_redacted_header(&self, header_name: &HeaderName) -> bool {
+        self.headers.contains(header_name)
+    }
+
+    pub(crate) fn marker_str(&self) -> &str {
+        &self.marker
+    }
+
+    fn redact_path_ranges<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let mut path = Cow::Borrowed(path);
+        for redactor in &self.path_redactors {
+            let mut ranges = redactor(&path);
+            ranges.sort_by_key(|range| range.start);
+            let mut redacted = String::with_capacity(path.len());
+            let mut last = 0;
+            for range in ranges {
+                // E.g. nested capture groups overlap with the enclosing one, already redacted.
+                if range.is_empty() || range.start < last || path.get(range.clone()).is_none() {
+                    continue;
+                }
+                redacted.push_str(&path[last..range.start]);
+                redacted.push_str(&self.marker);
+                last = range.end;
+            }
+            if last > 0 {
+                redacted.push_str(&path[last..]);
+                path = Cow::Owned(redacted);
+            }
+        }
+        path
+    }
+
+    fn redact_query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        let is_redacted = |pair: &str| {
+            let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+            let name = percent_decode(name);
+            self.query_params.iter().any(|param| *param == name)
+        };
+        if !query.split('&').any(is_redacted) {
+            return Cow::Borrowed(query);
+        }
+        let pairs: Vec<Cow<'_, str>> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if is_redacted(pair) => format!("{name}={}", self.marker).into(),
+                _ => pair.into(),
+            })
+            .collect();
+        pairs.join("&").into()
+    }
+}
+
+/// Decode a `application/x-www-form-urlencoded` query parameter name, e.g. `api%5Fkey` into
+/// `api_key`.
+///
+/// Invalid escape sequences are kept as-is.
+fn percent_decode(name: &str) -> Cow<'_, str> {
+    if !name.contains(['%', '+']) {
+        return Cow::Borrowed(name);
+    }
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let byte = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(byte) = byte {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encoded_query_param_names_are_redacted() {
+        let redaction = RedactionPolicy::new().redact_query_param("api_key");
+        assert_eq!(
+            redaction.redact_target("/?api%5Fkey=secret&api%5fkey=secret&lang=en"),
+            "/?api%5Fkey=[REDACTED]&api%5fkey=[REDACTED]&lang=en"
+        );
+    }
+
+    #[test]
+    fn invalid_escape_sequences_are_kept() {
+        assert_eq!(percent_decode("a%zzb%4"), "a%zzb%4");
+        assert_eq!(percent_decode("a+b%21"), "a b!");
+    }
+
+    #[test]
+    fn path_redactors_are_applied_in_order() {
+        let redaction = RedactionPolicy::new()
+            .redact_path_with(|path| path.find("secret").map(|i| i..i + 6).into_iter().collect())
+            .redact_path_with(|path| path.find("***").map(|i| i..i + 3).into_iter().collect())
+            .marker("***");
+        assert_eq!(redaction.redact_target("/v1/secret?a=b"), "/v1/***?a=b");
+        assert_eq!(redaction.redact_target("/public"), "/public");
+    }
+
+    #[test]
+    fn overlapping_and_invalid_ranges_are_ignored() {
+        let redaction = RedactionPolicy::new().redact_path_with(|_| vec![3..5, 1..4, 0..0, 6..99]);
+        assert_eq!(redaction.redact_target("/abcdef"), "/[REDACTED]def");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn path_patterns_redact_capture_groups_or_whole_matches() {
+        let redaction = RedactionPolicy::new()
+            .redact_path(Regex::new("^/users/([^/]+)/tokens/([^/]+)").unwrap())
+            .redact_path(Regex::new("[0-9a-f]{32}").unwrap());
+        assert_eq!(
+            redaction.redact_target("/users/bob/tokens/xyz/files/0123456789abcdef0123456789abcdef"),
+            "/users/[REDACTED]/tokens/[REDACTED]/files/[REDACTED]"
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn nested_capture_groups_are_redacted_once() {
+        let redaction = RedactionPolicy::new().redact_path(Regex::new("^/a/((b)c)").unwrap());
+        assert_eq!(redaction.redact_target("/a/bc/d"), "/a/[REDACTED]/d");
+    }
+}
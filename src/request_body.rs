@@ -0,0 +1,64 @@
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use actix_web::web::Bytes;
+use actix_web::HttpMessage;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracing::Span;
+
+/// Record the `Content-Length` and `Content-Type` of the request on its root span and wrap
+/// its payload to keep track of the number of bytes actually read by the application.
+pub(crate) fn track_request_body(request: &mut ServiceRequest, span: &Span) {
+    let headers = request.headers();
+    if let Some(content_length) = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<u64>().ok())
+    {
+        span.record("http.request.content_length", content_length);
+    }
+    if let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|h| h.to_str().ok()) {
+        span.record("http.request.content_type", content_type);
+    }
+
+    let payload = PayloadSpan {
+        payload: request.take_payload(),
+        span: span.clone(),
+        bytes_read: 0,
+    };
+    request.set_payload(Payload::from(
+        Box::pin(payload) as Pin<Box<dyn Stream<Item = _>>>
+    ));
+}
+
+/// A request payload that records the number of bytes read so far on the root span.
+///
+/// The field is updated on every chunk: the application might stop reading the payload
+/// halfway through, or hold on to it well after the response has been sent.
+struct PayloadSpan {
+    payload: Payload,
+    span: Span,
+    bytes_read: u64,
+}
+
+impl Stream for PayloadSpan {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.payload).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.bytes_read += chunk.len() as u64;
+                this.span.record("http.request.body.size", this.bytes_read);
+            }
+            Poll::Ready(None) => {
+                this.span.record("http.request.body.size", this.bytes_read);
+            }
+            _ => {}
+        }
+        poll
+    }
+}
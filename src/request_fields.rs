@@ -0,0 +1,116 @@
+use crate::client_address::client_address;
+use crate::config::RequestContext;
+use crate::headers::render_headers;
+use crate::root_span_macro::private::{
+    http_method_str, http_scheme, server_address_and_port, split_target,
+};
+use crate::RequestId;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Version;
+use actix_web::HttpMessage;
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// The values of the fields recorded on the root span by [`root_span!`] when the request
+/// comes in.
+///
+/// They are computed once, here, for both the root span and the summary event emitted for
+/// failed and slow requests - see [`TracingLoggerBuilder::promote_failed_requests`].
+///
+/// [`root_span!`]: crate::root_span!
+/// [`TracingLoggerBuilder::promote_failed_requests`]: crate::TracingLoggerBuilder::promote_failed_requests
+#[doc(hidden)]
+pub struct RequestFields {
+    /// `true` if the legacy field names should be populated - see
+    /// [`SemanticConventions`](crate::SemanticConventions).
+    pub legacy: bool,
+    /// `true` if the stable field names should be populated.
+    pub stable: bool,
+    pub method: Cow<'static, str>,
+    pub route: Cow<'static, str>,
+    pub version: Version,
+    pub scheme: Cow<'static, str>,
+    pub host: String,
+    pub server_address: String,
+    pub server_port: Option<u16>,
+    pub peer_addr: Option<SocketAddr>,
+    pub client_address: String,
+    pub is_forwarded_header_ignored: bool,
+    pub user_agent: String,
+    /// The request target, scrubbed according to the configured redaction policy.
+    pub target: String,
+    /// The captured request headers, already rendered - `None` if none was captured.
+    pub request_headers: Option<String>,
+    pub request_id: RequestId,
+}
+
+impl RequestFields {
+    /// The fields of `request`, computed the first time they are needed and stored in its
+    /// extensions: they are shared by [`root_span!`](crate::root_span!) and the summary event.
+    pub fn of(request: &ServiceRequest, context: &RequestContext) -> Arc<Self> {
+        if let Some(fields) = request.extensions().get::<Arc<Self>>() {
+            return fields.clone();
+        }
+        let fields = Arc::new(Self::new(request, context));
+        request.extensions_mut().insert(fields.clone());
+        fields
+    }
+
+    fn new(request: &ServiceRequest, context: &RequestContext) -> Self {
+        let config = &context.config;
+        let connection_info = request.connection_info();
+        let (server_address, server_port) =
+            server_address_and_port(connection_info.host(), connection_info.scheme());
+        let client_address = client_address(
+            request,
+            &config.trusted_proxies,
+            connection_info.realip_remote_addr(),
+        );
+        let target = request
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("");
+        Self {
+            legacy: config.semantic_conventions.emit_legacy(),
+            stable: config.semantic_conventions.emit_stable(),
+            method: http_method_str(request.method()),
+            route: request
+                .match_pattern()
+                .map(Into::into)
+                .unwrap_or_else(|| "default".into()),
+            version: request.version(),
+            scheme: http_scheme(connection_info.scheme()),
+            host: connection_info.host().to_owned(),
+            server_address: server_address.to_owned(),
+            server_port,
+            peer_addr: request.peer_addr(),
+            client_address: client_address.address,
+            is_forwarded_header_ignored: client_address.is_forwarded_header_ignored,
+            user_agent: request
+                .headers()
+                .get("User-Agent")
+                .map(|h| h.to_str().unwrap_or(""))
+                .unwrap_or("")
+                .to_owned(),
+            target: config.redaction.redact_target(target).into_owned(),
+            request_headers: render_headers(
+                request.headers(),
+                &config.captured_request_headers,
+                &config.redaction,
+            ),
+            request_id: request.extensions().get::<RequestId>().cloned().unwrap(),
+        }
+    }
+
+    /// The path of the request target (`url.path`).
+    pub fn url_path(&self) -> &str {
+        split_target(&self.target).0
+    }
+
+    /// The query string of the request target (`url.query`), if any.
+    pub fn url_query(&self) -> Option<&str> {
+        split_target(&self.target).1
+    }
+}
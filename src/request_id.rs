@@ -1,6 +1,9 @@
+use actix_web::http::header::HeaderValue;
 use actix_web::{dev::Payload, HttpMessage};
 use actix_web::{FromRequest, HttpRequest, ResponseError};
+use std::convert::TryFrom;
 use std::future::{ready, Ready};
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// A unique identifier generated for each incoming request.
@@ -11,6 +14,7 @@ use uuid::Uuid;
 /// # Usage
 /// ```rust
 /// use actix_web::get;
+/// use std::convert::TryFrom;
 /// use tracing_actix_web::RequestId;
 /// use uuid::Uuid;
 ///
@@ -21,45 +25,153 @@ use uuid::Uuid;
 ///
 /// #[get("/2")]
 /// async fn index2(request_id: RequestId) -> String {
-///  let uuid: Uuid = request_id.into();
-///   format!("{}", uuid)
+///   match Uuid::try_from(request_id) {
+///     Ok(uuid) => format!("{}", uuid),
+///     Err(request_id) => format!("Not a UUID: {}", request_id),
+///   }
 /// }
 /// ```
 ///
+/// # Representations
+///
+/// Request ids generated by `tracing-actix-web` are UUIDs, but that's not necessarily the case for
+/// ids generated by a custom [`RequestIdGenerator`] or inherited from an incoming request.  
+/// A `RequestId` can be:
+///
+/// - a UUID - see [`RequestId::as_uuid`];
+/// - an opaque string, e.g. an id assigned by your edge proxy - see [`RequestId::opaque`] and [`RequestId::as_str`];
+/// - a compact 64-bit integer, e.g. a Snowflake id - see [`RequestId::as_u64`].
+///
 /// Optionally, using the `uuid_v7` feature flag will allow [`RequestId`] to use UUID v7 instead of the currently used UUID v4.
-#[derive(Clone, Copy, Debug)]
-pub struct RequestId(Uuid);
+/// You can also choose the format of request ids at runtime, using a [`RequestIdGenerator`].
+///
+/// The request id can also be inherited from a header of the incoming request - see
+/// [`TracingLoggerBuilder::inherit_request_id_from`].
+///
+/// [`TracingLoggerBuilder::inherit_request_id_from`]: crate::TracingLoggerBuilder::inherit_request_id_from
+#[derive(Clone, Debug)]
+pub struct RequestId {
+    repr: Repr,
+    inherited: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Repr {
+    Uuid(Uuid),
+    Opaque(Arc<str>),
+    Compact(u64),
+}
 
 impl RequestId {
     pub(crate) fn generate() -> Self {
         #[cfg(not(feature = "uuid_v7"))]
         {
-            Self(Uuid::new_v4())
+            UuidV4Generator.generate()
         }
         #[cfg(feature = "uuid_v7")]
         {
-            Self(Uuid::now_v7())
+            UuidV7Generator.generate()
+        }
+    }
+
+    /// Build a request id out of an opaque string - e.g. an identifier assigned by your edge proxy.
+    pub fn opaque(id: impl Into<Arc<str>>) -> Self {
+        Self {
+            repr: Repr::Opaque(id.into()),
+            inherited: false,
+        }
+    }
+
+    /// Parse a request id out of the value of an incoming request header.
+    ///
+    /// UUIDs are always accepted. Other values are accepted as opaque strings if they are
+    /// not empty and no longer than `max_opaque_len` bytes.  
+    /// It returns `None` if the header value is not acceptable.
+    pub(crate) fn inherit(header_value: &HeaderValue, max_opaque_len: usize) -> Option<Self> {
+        let value = header_value.to_str().ok()?;
+        let repr = match Uuid::try_parse(value) {
+            Ok(id) => Repr::Uuid(id),
+            Err(_) if !value.is_empty() && value.len() <= max_opaque_len => {
+                Repr::Opaque(value.into())
+            }
+            Err(_) => return None,
+        };
+        Some(Self {
+            repr,
+            inherited: true,
+        })
+    }
+
+    /// The request id as a UUID, if it is one.
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match &self.repr {
+            Repr::Uuid(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// The request id as a string slice, if it is an opaque string.
+    ///
+    /// Use the `Display` implementation of `RequestId` to get a string out of
+    /// any request id, regardless of its representation.
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Opaque(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The request id as an integer, if it uses the compact 64-bit representation.
+    pub fn as_u64(&self) -> Option<u64> {
+        match &self.repr {
+            Repr::Compact(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// `true` if the request id was taken from a header of the incoming request,
+    /// `false` if it was generated by [`TracingLogger`].
+    ///
+    /// [`TracingLogger`]: crate::TracingLogger
+    pub fn is_inherited(&self) -> bool {
+        self.inherited
+    }
+}
+
+impl From<Uuid> for RequestId {
+    fn from(id: Uuid) -> Self {
+        Self {
+            repr: Repr::Uuid(id),
+            inherited: false,
         }
     }
 }
 
-impl std::ops::Deref for RequestId {
-    type Target = Uuid;
+/// It fails, giving the request id back, if the request id is not a UUID.
+impl TryFrom<RequestId> for Uuid {
+    type Error = RequestId;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    fn try_from(request_id: RequestId) -> Result<Self, Self::Error> {
+        request_id.as_uuid().ok_or(request_id)
     }
 }
 
-impl From<RequestId> for Uuid {
-    fn from(r: RequestId) -> Self {
-        r.0
+impl From<u64> for RequestId {
+    fn from(id: u64) -> Self {
+        Self {
+            repr: Repr::Compact(id),
+            inherited: false,
+        }
     }
 }
 
 impl std::fmt::Display for RequestId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        match &self.repr {
+            Repr::Uuid(id) => write!(f, "{}", id),
+            Repr::Opaque(id) => write!(f, "{}", id),
+            Repr::Compact(id) => write!(f, "{}", id),
+        }
     }
 }
 
@@ -71,12 +183,79 @@ impl FromRequest for RequestId {
         ready(
             req.extensions()
                 .get::<RequestId>()
-                .copied()
+                .cloned()
                 .ok_or(RequestIdExtractionError { _priv: () }),
         )
     }
 }
 
+/// `RequestIdGenerator` determines the format of the [`RequestId`]s generated by [`TracingLogger`]
+/// for incoming requests.
+///
+/// `tracing-actix-web` provides [`UuidV4Generator`] out of the box, as well as `UuidV7Generator`
+/// if the `uuid_v7` feature flag is enabled. You can pick one at runtime, or bring your own,
+/// using [`TracingLoggerBuilder::request_id_generator`].
+///
+/// # Usage
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::{SystemTime, UNIX_EPOCH};
+/// use tracing_actix_web::{RequestId, RequestIdGenerator, TracingLogger};
+///
+/// /// A (simplified) Snowflake-style generator: a millisecond timestamp, followed
+/// /// by the machine id and a sequence number.
+/// pub struct SnowflakeGenerator {
+///     machine_id: u64,
+///     sequence: AtomicU64,
+/// }
+///
+/// impl RequestIdGenerator for SnowflakeGenerator {
+///     fn generate(&self) -> RequestId {
+///         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+///         let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) & 0xFFF;
+///         RequestId::from((timestamp << 22) | ((self.machine_id & 0x3FF) << 12) | sequence)
+///     }
+/// }
+///
+/// let logger = TracingLogger::builder()
+///     .request_id_generator(SnowflakeGenerator { machine_id: 1, sequence: AtomicU64::new(0) })
+///     .build();
+/// ```
+///
+/// [`TracingLogger`]: crate::TracingLogger
+/// [`TracingLoggerBuilder::request_id_generator`]: crate::TracingLoggerBuilder::request_id_generator
+pub trait RequestIdGenerator: Send + Sync + 'static {
+    fn generate(&self) -> RequestId;
+}
+
+/// A [`RequestIdGenerator`] for random UUIDs (version 4).
+///
+/// It is the default generator, unless the `uuid_v7` feature flag is enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidV4Generator;
+
+impl RequestIdGenerator for UuidV4Generator {
+    fn generate(&self) -> RequestId {
+        Uuid::new_v4().into()
+    }
+}
+
+/// A [`RequestIdGenerator`] for time-ordered UUIDs (version 7).
+///
+/// It is only available, and it is the default generator, if the `uuid_v7` feature flag is
+/// enabled.
+#[cfg(feature = "uuid_v7")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidV7Generator;
+
+#[cfg(feature = "uuid_v7")]
+impl RequestIdGenerator for UuidV7Generator {
+    fn generate(&self) -> RequestId {
+        Uuid::now_v7().into()
+    }
+}
+
 #[derive(Debug)]
 /// Error returned by the [`RequestId`] extractor when it fails to retrieve
 /// the current request id from request-local storage.
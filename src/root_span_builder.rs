@@ -1,17 +1,223 @@
+use crate::backtrace::record_stacktrace;
+use crate::config::{Config, RequestContext};
+use crate::error_chain::{error_source, record_exception_chain};
+use crate::headers::record_headers;
 use crate::root_span;
+use crate::status_classifier::StatusClass;
+use crate::traced_error::record_traced_error;
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::http::StatusCode;
-use actix_web::{Error, ResponseError};
+use actix_web::Error;
+use std::marker::PhantomData;
+use std::time::Duration;
 use tracing::Span;
 
 /// `RootSpanBuilder` allows you to customise the root span attached by
 /// [`TracingLogger`] to incoming requests.
 ///
+/// If your builder needs to hold some state (e.g. values read from your configuration files),
+/// check out [`StatefulRootSpanBuilder`].
+///
 /// [`TracingLogger`]: crate::TracingLogger
 pub trait RootSpanBuilder {
     fn on_request_start(request: &ServiceRequest) -> Span;
     fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>);
+
+    /// Invoked when the streaming of the response body ends - either because it completed,
+    /// failed or was dropped early (e.g. the client disconnected).
+    ///
+    /// For streaming endpoints (e.g. SSE or large downloads) this is the true end of the request,
+    /// while `on_request_end` is invoked as soon as the response head is ready.
+    /// It does nothing by default.
+    fn on_body_end(span: Span, body_end: &BodyEnd) {
+        let _ = (span, body_end);
+    }
+}
+
+/// Information about the end of the streaming of a response body, passed to
+/// [`RootSpanBuilder::on_body_end`] and [`StatefulRootSpanBuilder::on_body_end`].
+///
+/// ```rust
+/// use actix_web::body::MessageBody;
+/// use actix_web::dev::{ServiceResponse, ServiceRequest};
+/// use actix_web::Error;
+/// use tracing_actix_web::{BodyEnd, BodyOutcome, DefaultRootSpanBuilder, RootSpanBuilder};
+/// use tracing::Span;
+///
+/// pub struct DownloadRootSpanBuilder;
+///
+/// impl RootSpanBuilder for DownloadRootSpanBuilder {
+///     fn on_request_start(request: &ServiceRequest) -> Span {
+///         tracing_actix_web::root_span!(request, download.duration_ms = tracing::field::Empty)
+///     }
+///
+///     fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+///         DefaultRootSpanBuilder::on_request_end(span, outcome);
+///     }
+///
+///     fn on_body_end(span: Span, body_end: &BodyEnd) {
+///         if let BodyOutcome::Completed = body_end.outcome() {
+///             span.record("download.duration_ms", body_end.elapsed().as_millis() as u64);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BodyEnd {
+    outcome: BodyOutcome,
+    bytes_sent: u64,
+    elapsed: Duration,
+}
+
+impl BodyEnd {
+    pub(crate) fn new(outcome: BodyOutcome, bytes_sent: u64, elapsed: Duration) -> Self {
+        Self {
+            outcome,
+            bytes_sent,
+            elapsed,
+        }
+    }
+
+    /// How the streaming of the response body ended.
+    pub fn outcome(&self) -> &BodyOutcome {
+        &self.outcome
+    }
+
+    /// The number of bytes of the response body handed over to `actix-web`.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// The time elapsed since the request was received by [`TracingLogger`].
+    ///
+    /// [`TracingLogger`]: crate::TracingLogger
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// How the streaming of a response body ended - see [`BodyEnd::outcome`].
+///
+/// The error of a failed body is not exposed: the body type of your application is not
+/// required to produce errors that can be inspected. It is recorded on the root span
+/// (`http.response.body.error`) when it can be rendered - see [`DefaultRootSpanBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyOutcome {
+    /// The response body was streamed to its end.
+    Completed,
+    /// The response body failed midway with an error.
+    Failed,
+    /// The response body was dropped before its end - e.g. because the client disconnected.
+    Aborted,
+}
+
+impl BodyOutcome {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            BodyOutcome::Completed => "completed",
+            BodyOutcome::Failed => "failed",
+            BodyOutcome::Aborted => "aborted",
+        }
+    }
+}
+
+/// `StatefulRootSpanBuilder` is the instance-based counterpart of [`RootSpanBuilder`]: it
+/// receives `&self`, therefore your builder can hold state - e.g. a redaction policy or an
+/// `Arc<Config>` populated at startup.
+///
+/// The trait is object-safe: [`TracingLogger::with_builder`] stores it as a trait object and
+/// shares it across all the workers of your application.  
+/// As a consequence, `on_request_end` cannot be generic over the response body type:
+/// the response is handed over to the builder with its body temporarily detached.
+///
+/// # Usage
+///
+/// ```rust
+/// use actix_web::dev::{ServiceResponse, ServiceRequest};
+/// use actix_web::{App, Error};
+/// use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder, StatefulRootSpanBuilder, TracingLogger};
+/// use tracing::Span;
+///
+/// pub struct TenantRootSpanBuilder {
+///     tenant_header: String,
+/// }
+///
+/// impl StatefulRootSpanBuilder for TenantRootSpanBuilder {
+///     fn on_request_start(&self, request: &ServiceRequest) -> Span {
+///         let tenant = request
+///             .headers()
+///             .get(self.tenant_header.as_str())
+///             .and_then(|h| h.to_str().ok())
+///             .unwrap_or("");
+///         tracing_actix_web::root_span!(request, tenant)
+///     }
+///
+///     fn on_request_end(&self, span: Span, outcome: &Result<ServiceResponse<()>, Error>) {
+///         DefaultRootSpanBuilder::on_request_end(span, outcome);
+///     }
+/// }
+///
+/// let builder = TenantRootSpanBuilder { tenant_header: "x-tenant-id".into() };
+/// let app = App::new().wrap(TracingLogger::with_builder(builder));
+/// ```
+///
+/// Any [`RootSpanBuilder`] can be used where a `StatefulRootSpanBuilder` is expected
+/// by wrapping it in [`StatelessRootSpanBuilder`].
+///
+/// [`TracingLogger::with_builder`]: crate::TracingLogger::with_builder
+pub trait StatefulRootSpanBuilder: Send + Sync + 'static {
+    fn on_request_start(&self, request: &ServiceRequest) -> Span;
+    fn on_request_end(&self, span: Span, outcome: &Result<ServiceResponse<()>, Error>);
+
+    /// See [`RootSpanBuilder::on_body_end`]. It does nothing by default.
+    fn on_body_end(&self, span: Span, body_end: &BodyEnd) {
+        let _ = (span, body_end);
+    }
+}
+
+/// An adapter to use a [`RootSpanBuilder`] where a [`StatefulRootSpanBuilder`] is expected.
+///
+/// Like any [`StatefulRootSpanBuilder`], the wrapped builder gets the response with its body
+/// detached: use `TracingLogger<RootSpan>` directly if it needs to inspect the body.
+///
+/// ```rust
+/// use tracing_actix_web::{DefaultRootSpanBuilder, StatelessRootSpanBuilder, TracingLogger};
+///
+/// let logger = TracingLogger::with_builder(StatelessRootSpanBuilder::<DefaultRootSpanBuilder>::new());
+/// ```
+pub struct StatelessRootSpanBuilder<RootSpan: RootSpanBuilder> {
+    root_span_builder: PhantomData<fn() -> RootSpan>,
+}
+
+impl<RootSpan: RootSpanBuilder> StatelessRootSpanBuilder<RootSpan> {
+    pub fn new() -> Self {
+        Self {
+            root_span_builder: PhantomData,
+        }
+    }
+}
+
+impl<RootSpan: RootSpanBuilder> Default for StatelessRootSpanBuilder<RootSpan> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RootSpan: RootSpanBuilder + 'static> StatefulRootSpanBuilder
+    for StatelessRootSpanBuilder<RootSpan>
+{
+    fn on_request_start(&self, request: &ServiceRequest) -> Span {
+        RootSpan::on_request_start(request)
+    }
+
+    fn on_request_end(&self, span: Span, outcome: &Result<ServiceResponse<()>, Error>) {
+        RootSpan::on_request_end(span, outcome)
+    }
+
+    fn on_body_end(&self, span: Span, body_end: &BodyEnd) {
+        RootSpan::on_body_end(span, body_end)
+    }
 }
 
 /// The default [`RootSpanBuilder`] for [`TracingLogger`].
@@ -20,60 +226,159 @@ pub trait RootSpanBuilder {
 /// - HTTP method (`http.method`);
 /// - HTTP route (`http.route`), with templated parameters;
 /// - HTTP version (`http.flavor`);
-/// - HTTP host (`http.host`);
-/// - Client IP (`http.client_ip`);
+/// - HTTP host (`http.host`), as specified by the client - it may include a port;
+/// - Server address (`server.address`), i.e. the HTTP host without the port;
+/// - Server port (`server.port`), either explicit in the HTTP host or the default one for the scheme;
+/// - Client IP (`http.client_ip`), as reported by `Forwarded`/`X-Forwarded-For` headers if present -
+///   see [`TracingLoggerBuilder::trusted_proxy`](crate::TracingLoggerBuilder::trusted_proxy)
+///   to only trust the headers set by your own proxies;
+/// - Whether forwarding headers were ignored because they were not set by a trusted proxy
+///   (`client.forwarded_header_ignored`);
+/// - Address (`network.peer.address`) and port (`network.peer.port`) of the TCP peer, i.e. the
+///   client or the last proxy in front of your application;
 /// - User agent (`http.user_agent`);
-/// - Request path (`http.target`);
+/// - Request path (`http.target`), scrubbed according to the configured [redaction policy](crate::RedactionPolicy);
+/// - Request (`http.request.headers`) and response (`http.response.headers`) headers, if
+///   enabled via [`TracingLoggerBuilder::capture_request_header`](crate::TracingLoggerBuilder::capture_request_header)
+///   and [`TracingLoggerBuilder::capture_response_header`](crate::TracingLoggerBuilder::capture_response_header);
 /// - Status code (`http.status_code`);
+/// - Time it took to produce the response head, in milliseconds (`http.server.duration_ms`).
+///   It does not include the time spent streaming the response body;
+/// - `Content-Length` (`http.request.content_length`), `Content-Type` (`http.request.content_type`)
+///   and number of bytes read from the request body (`http.request.body.size`), if enabled via
+///   [`TracingLoggerBuilder::capture_request_body_metadata`](crate::TracingLoggerBuilder::capture_request_body_metadata);
+/// - Number of bytes in the response body that were handed over to `actix-web` (`http.response.body.size`);
+/// - Whether the response body was streamed to its end (`completed`), failed midway (`failed`)
+///   or was dropped before its end (`aborted`) - e.g. because the client disconnected
+///   (`http.response.body.outcome`);
+/// - `Display` representation of the error that caused the response body to fail, if any (`http.response.body.error`).
+///   It is only recorded for the body types boxed by `actix-web` (`BoxBody` and `EitherBody`), as well as bodies
+///   failing with an [`std::io::Error`];
 /// - [Request id](crate::RequestId) (`request_id`);
-/// - `Display` (`exception.message`) and `Debug` (`exception.details`) representations of the error, if there was an error;
+/// - Whether the request id was inherited from the incoming request (`request_id.inherited`);
+/// - Why the request was sampled (`sampling.decision`), if a [sampling policy](crate::SamplingPolicy) is configured;
+/// - `Display` (`exception.message`) and `Debug` (`exception.details`) representations of the error, if there was an error.
+///   If the request handler panicked, `exception.message` records the panic message instead;
+/// - Whether the request failed (`otel.status_code`), according to the configured
+///   [`StatusClassifier`](crate::StatusClassifier);
+/// - Causes of the error (`exception.chain`), if they can be reached - see
+///   [`TracingLoggerBuilder::exception_chain_max_depth`](crate::TracingLoggerBuilder::exception_chain_max_depth);
+/// - A backtrace (`exception.stacktrace`) for failed requests, if enabled - see
+///   [`TracingLoggerBuilder::capture_server_error_backtraces`](crate::TracingLoggerBuilder::capture_server_error_backtraces);
+/// - Type (`error.type`), code (`error.code`) and retryability (`error.retryable`) of the error,
+///   if it implements [`TracedError`](crate::TracedError);
 /// - [Request id](crate::RequestId) (`request_id`);
 /// - [OpenTelemetry trace identifier](https://github.com/open-telemetry/opentelemetry-specification/blob/main/specification/overview.md#spancontext) (`trace_id`). Empty if the feature is not enabled;
 /// - OpenTelemetry span kind, set to `server` (`otel.kind`).
 ///
 /// All field names follow [OpenTelemetry's semantic convention](https://github.com/open-telemetry/opentelemetry-specification/tree/main/specification/trace/semantic_conventions).
+/// The names listed above are the legacy ones: check out [`SemanticConventions`](crate::SemanticConventions)
+/// to switch to the stable HTTP conventions - e.g. `http.request.method` instead of `http.method`.
 ///
 /// [`TracingLogger`]: crate::TracingLogger
 pub struct DefaultRootSpanBuilder;
 
 impl RootSpanBuilder for DefaultRootSpanBuilder {
     fn on_request_start(request: &ServiceRequest) -> Span {
-        root_span!(level = crate::Level::INFO, request)
+        root_span!(request)
     }
 
     fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        let context = RequestContext::of_outcome(outcome);
+        let config = &context.config;
+        if !config.captured_response_headers.is_empty() {
+            let allowlist = &config.captured_response_headers;
+            let redaction = &config.redaction;
+            match outcome {
+                Ok(response) => record_headers(
+                    &span,
+                    "http.response.headers",
+                    response.headers(),
+                    allowlist,
+                    redaction,
+                ),
+                // The error response is built by `actix-web` later on: we build it here as well
+                // to inspect its headers.
+                Err(error) => record_headers(
+                    &span,
+                    "http.response.headers",
+                    error.error_response().headers(),
+                    allowlist,
+                    redaction,
+                ),
+            }
+        }
         match &outcome {
             Ok(response) => {
+                // use the status code already constructed for the outgoing HTTP response
+                let status_code = response.status();
+                let class = context.status_class(status_code);
                 if let Some(error) = response.response().error() {
-                    // use the status code already constructed for the outgoing HTTP response
-                    handle_error(span, response.status(), error.as_response_error());
+                    handle_error(&span, config, status_code, class, error);
                 } else {
-                    let code: i32 = response.response().status().as_u16().into();
-                    span.record("http.status_code", code);
-                    span.record("otel.status_code", "OK");
+                    record_status_code(&span, config, status_code);
                 }
+                record_status_class(&span, class);
             }
             Err(error) => {
-                let response_error = error.as_response_error();
-                handle_error(span, response_error.status_code(), response_error);
+                let status_code = error.as_response_error().status_code();
+                let class = context.status_class(status_code);
+                handle_error(&span, config, status_code, class, error);
+                record_status_class(&span, class);
             }
         };
     }
 }
 
-fn handle_error(span: Span, status_code: StatusCode, response_error: &dyn ResponseError) {
+/// Record whether the request failed, according to the configured
+/// [`StatusClassifier`](crate::StatusClassifier).
+fn record_status_class(span: &Span, class: StatusClass) {
+    match class {
+        StatusClass::Success | StatusClass::ClientError => span.record("otel.status_code", "OK"),
+        StatusClass::Failure => span.record("otel.status_code", "ERROR"),
+    };
+}
+
+/// Record the status code on the field(s) mandated by the configured semantic conventions.
+fn record_status_code(span: &Span, config: &Config, status_code: StatusCode) {
+    let code: i32 = status_code.as_u16().into();
+    let semantic_conventions = config.semantic_conventions;
+    if semantic_conventions.emit_legacy() {
+        span.record("http.status_code", code);
+    }
+    if semantic_conventions.emit_stable() {
+        span.record("http.response.status_code", code);
+    }
+}
+
+fn handle_error(
+    span: &Span,
+    config: &Config,
+    status_code: StatusCode,
+    class: StatusClass,
+    error: &Error,
+) {
+    let response_error = error.as_response_error();
     // pre-formatting errors is a workaround for https://github.com/tokio-rs/tracing/issues/1565
     let display = format!("{response_error}");
     let debug = format!("{response_error:?}");
     span.record("exception.message", tracing::field::display(display));
     span.record("exception.details", tracing::field::display(debug));
-    let code: i32 = status_code.as_u16().into();
-
-    span.record("http.status_code", code);
-
-    if status_code.is_client_error() {
-        span.record("otel.status_code", "OK");
-    } else {
-        span.record("otel.status_code", "ERROR");
+    record_status_code(span, config, status_code);
+    let traced_error = config
+        .traced_errors
+        .iter()
+        .find_map(|downcast| downcast(error));
+    if let Some(traced_error) = traced_error {
+        record_traced_error(span, traced_error);
+    }
+    record_exception_chain(
+        span,
+        error_source(error, traced_error),
+        config.exception_chain_max_depth,
+        config.exception_chain_events,
+    );
+    if config.capture_server_error_backtraces && class == StatusClass::Failure {
+        record_stacktrace(span, traced_error);
     }
 }
@@ -52,7 +52,8 @@
 /// let app_id = "XYZ";
 /// tracing_actix_web::root_span!(request, app_id);
 ///
-/// // Use a custom level, `DEBUG`, instead of the default (`INFO`).
+/// // Use a custom level, `DEBUG`, instead of the default (`INFO`, unless a different
+/// // level was specified via `TracingLoggerBuilder::span_level`).
 /// tracing_actix_web::root_span!(level = Level::DEBUG, request);
 ///
 /// // All together
@@ -71,58 +72,83 @@ macro_rules! root_span {
     };
     // One or more additional fields, comma separated, without a level
     ($request:ident, $($field:tt)*) => {
-        $crate::root_span!(level = $crate::Level::INFO, $request, $($field)*)
+        $crate::root_span!(@span None, $request, $($field)*)
     };
     // One or more additional fields, comma separated
     (level = $lvl:expr, $request:ident, $($field:tt)*) => {
+        $crate::root_span!(@span Some($lvl), $request, $($field)*)
+    };
+    // Internal rule - the level is `None` if it should be taken from the configuration
+    (@span $lvl:expr, $request:ident, $($field:tt)*) => {
         {
-            let user_agent = $request
-                .headers()
-                .get("User-Agent")
-                .map(|h| h.to_str().unwrap_or(""))
-                .unwrap_or("");
-            let http_route: std::borrow::Cow<'static, str> = $request
-                .match_pattern()
-                .map(Into::into)
-                .unwrap_or_else(|| "default".into());
-            let http_method = $crate::root_span_macro::private::http_method_str($request.method());
-            let connection_info = $request.connection_info();
-            let request_id = $crate::root_span_macro::private::get_request_id($request);
+            let context = $crate::root_span_macro::private::request_context(&$request);
+            let level: Option<$crate::Level> = $lvl;
+            let level = level.unwrap_or_else(|| $crate::root_span_macro::private::span_level(&context));
+            let fields = $crate::root_span_macro::private::RequestFields::of(&$request, &context);
+            let url_query = fields.url_query();
 
             macro_rules! inner_span {
                 ($level:expr) => {
                     $crate::root_span_macro::private::tracing::span!(
                         $level,
                         "HTTP request",
-                        http.method = %http_method,
-                        http.route = %http_route,
-                        http.flavor = %$crate::root_span_macro::private::http_flavor($request.version()),
-                        http.scheme = %$crate::root_span_macro::private::http_scheme(connection_info.scheme()),
-                        http.host = %connection_info.host(),
-                        http.client_ip = %$request.connection_info().realip_remote_addr().unwrap_or(""),
-                        http.user_agent = %user_agent,
-                        http.target = %$request.uri().path_and_query().map(|p| p.as_str()).unwrap_or(""),
+                        http.method = $crate::root_span_macro::private::display_if(fields.legacy, &fields.method),
+                        http.request.method = $crate::root_span_macro::private::display_if(fields.stable, &fields.method),
+                        http.route = %fields.route,
+                        http.flavor = $crate::root_span_macro::private::display_if(fields.legacy, $crate::root_span_macro::private::http_flavor(fields.version)),
+                        network.protocol.version = $crate::root_span_macro::private::display_if(fields.stable, $crate::root_span_macro::private::network_protocol_version(fields.version)),
+                        http.scheme = $crate::root_span_macro::private::display_if(fields.legacy, &fields.scheme),
+                        url.scheme = $crate::root_span_macro::private::display_if(fields.stable, &fields.scheme),
+                        http.host = $crate::root_span_macro::private::display_if(fields.legacy, &fields.host),
+                        server.address = %fields.server_address,
+                        server.port = fields.server_port,
+                        network.peer.address = fields.peer_addr.map(|addr| $crate::root_span_macro::private::tracing::field::display(addr.ip())),
+                        network.peer.port = fields.peer_addr.map(|addr| addr.port()),
+                        http.client_ip = $crate::root_span_macro::private::display_if(fields.legacy, &fields.client_address),
+                        client.address = $crate::root_span_macro::private::display_if(fields.stable, &fields.client_address),
+                        client.forwarded_header_ignored = fields.is_forwarded_header_ignored.then_some(true),
+                        http.user_agent = $crate::root_span_macro::private::display_if(fields.legacy, &fields.user_agent),
+                        user_agent.original = $crate::root_span_macro::private::display_if(fields.stable, &fields.user_agent),
+                        http.target = $crate::root_span_macro::private::display_if(fields.legacy, &fields.target),
+                        url.path = $crate::root_span_macro::private::display_if(fields.stable, fields.url_path()),
+                        url.query = $crate::root_span_macro::private::display_if(fields.stable && url_query.is_some(), url_query.unwrap_or("")),
                         http.status_code = $crate::root_span_macro::private::tracing::field::Empty,
-                        otel.name = %format!("{} {}", http_method, http_route),
+                        http.response.status_code = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.server.duration_ms = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.request.headers = fields.request_headers.as_deref().map($crate::root_span_macro::private::tracing::field::display),
+                        http.response.headers = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.request.content_length = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.request.content_type = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.request.body.size = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.response.body.size = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.response.body.outcome = $crate::root_span_macro::private::tracing::field::Empty,
+                        http.response.body.error = $crate::root_span_macro::private::tracing::field::Empty,
+                        otel.name = %format!("{} {}", fields.method, fields.route),
                         otel.kind = "server",
                         otel.status_code = $crate::root_span_macro::private::tracing::field::Empty,
                         trace_id = $crate::root_span_macro::private::tracing::field::Empty,
-                        request_id = %request_id,
+                        request_id = %fields.request_id,
+                        request_id.inherited = fields.request_id.is_inherited(),
+                        sampling.decision = $crate::root_span_macro::private::tracing::field::Empty,
                         exception.message = $crate::root_span_macro::private::tracing::field::Empty,
                         // Not proper OpenTelemetry, but their terminology is fairly exception-centric
                         exception.details = $crate::root_span_macro::private::tracing::field::Empty,
+                        exception.chain = $crate::root_span_macro::private::tracing::field::Empty,
+                        exception.stacktrace = $crate::root_span_macro::private::tracing::field::Empty,
+                        error.type = $crate::root_span_macro::private::tracing::field::Empty,
+                        error.code = $crate::root_span_macro::private::tracing::field::Empty,
+                        error.retryable = $crate::root_span_macro::private::tracing::field::Empty,
                         $($field)*
                     )
                 };
             }
-            let span = match $lvl {
+            let span = match level {
                 $crate::Level::TRACE => inner_span!($crate::Level::TRACE),
                 $crate::Level::DEBUG => inner_span!($crate::Level::DEBUG),
                 $crate::Level::INFO => inner_span!($crate::Level::INFO),
                 $crate::Level::WARN => inner_span!($crate::Level::WARN),
                 $crate::Level::ERROR => inner_span!($crate::Level::ERROR),
             };
-            std::mem::drop(connection_info);
 
             // Previously, this line was instrumented with an opentelemetry-specific feature
             // flag check. However, this resulted in the feature flags being resolved in the crate
@@ -145,8 +171,13 @@ pub mod private {
     use crate::RequestId;
     use actix_web::dev::ServiceRequest;
     use actix_web::http::{Method, Version};
+    use actix_web::HttpMessage;
     use std::borrow::Cow;
+    use std::fmt::Display;
+    use tracing::field::DisplayValue;
 
+    pub use crate::config::RequestContext;
+    pub use crate::request_fields::RequestFields;
     pub use tracing;
 
     #[doc(hidden)]
@@ -219,6 +250,66 @@ pub mod private {
         }
     }
 
+    #[doc(hidden)]
+    pub fn request_context(request: &ServiceRequest) -> RequestContext {
+        RequestContext::of(&request.extensions())
+    }
+
+    #[doc(hidden)]
+    pub fn span_level(context: &RequestContext) -> tracing::Level {
+        context.config.span_level
+    }
+
+    /// Fields set to `None` are left empty, therefore they are not emitted.
+    #[doc(hidden)]
+    pub fn display_if<T: Display>(enabled: bool, value: T) -> Option<DisplayValue<T>> {
+        enabled.then(|| tracing::field::display(value))
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn network_protocol_version(version: Version) -> Cow<'static, str> {
+        match version {
+            Version::HTTP_09 => "0.9".into(),
+            Version::HTTP_10 => "1.0".into(),
+            Version::HTTP_11 => "1.1".into(),
+            Version::HTTP_2 => "2".into(),
+            Version::HTTP_3 => "3".into(),
+            other => format!("{other:?}").into(),
+        }
+    }
+
+    /// Split the `host` of a request (e.g. `example.com:8080` or `[::1]:8080`) into its address
+    /// and port, falling back to the default port of `scheme` if none is specified.
+    ///
+    /// If the port is specified but invalid (e.g. `example.com:abc`), no port is returned.
+    #[doc(hidden)]
+    pub fn server_address_and_port<'a>(host: &'a str, scheme: &str) -> (&'a str, Option<u16>) {
+        let (address, port) = match host.rsplit_once(':') {
+            // A bare IPv6 address, e.g. `[::1]`: the colon is part of the address.
+            Some((address, port)) if !port.ends_with(']') => (address, Some(port)),
+            _ => (host, None),
+        };
+        let port = match port {
+            Some(port) => port.parse().ok(),
+            None => match scheme {
+                "http" | "ws" => Some(80),
+                "https" | "wss" => Some(443),
+                _ => None,
+            },
+        };
+        (address.trim_start_matches('[').trim_end_matches(']'), port)
+    }
+
+    /// Split a request target into its path and its query string, if any.
+    #[doc(hidden)]
+    pub fn split_target(target: &str) -> (&str, Option<&str>) {
+        match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        }
+    }
+
     #[doc(hidden)]
     pub fn generate_request_id() -> RequestId {
         RequestId::generate()
@@ -226,8 +317,44 @@ pub mod private {
 
     #[doc(hidden)]
     pub fn get_request_id(request: &ServiceRequest) -> RequestId {
-        use actix_web::HttpMessage;
-
         request.extensions().get::<RequestId>().cloned().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::private::server_address_and_port;
+
+    #[test]
+    fn the_port_is_split_from_the_host() {
+        assert_eq!(
+            server_address_and_port("example.com:8080", "http"),
+            ("example.com", Some(8080))
+        );
+        assert_eq!(
+            server_address_and_port("[::1]:8443", "https"),
+            ("::1", Some(8443))
+        );
+    }
+
+    #[test]
+    fn the_default_port_of_the_scheme_is_used_if_none_is_specified() {
+        assert_eq!(
+            server_address_and_port("example.com", "https"),
+            ("example.com", Some(443))
+        );
+        assert_eq!(server_address_and_port("[::1]", "http"), ("::1", Some(80)));
+        assert_eq!(
+            server_address_and_port("example.com", "ftp"),
+            ("example.com", None)
+        );
+    }
+
+    #[test]
+    fn an_invalid_port_is_not_recorded() {
+        assert_eq!(
+            server_address_and_port("example.com:abc", "http"),
+            ("example.com", None)
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::PathPattern;
+use actix_web::dev::ServiceRequest;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// `SamplingPolicy` determines which requests get a root span when [`TracingLogger`] is
+/// configured for head-based sampling via [`TracingLoggerBuilder::sampling`].
+///
+/// The decision is taken when the request comes in, before the root span is created:
+///
+/// - if the incoming request carries a [W3C `traceparent` header](https://www.w3.org/TR/trace-context/#traceparent-header)
+///   with the `sampled` flag set, the request is always sampled - unless disabled via
+///   [`SamplingPolicy::respect_parent_decision`];
+/// - otherwise the request is sampled with the ratio of the first route-specific rule it
+///   matches (see [`SamplingPolicy::route_ratio`]), falling back to the default ratio.
+///
+/// Requests that are not sampled get a disabled root span ([`Span::none`]). They are still
+/// assigned a [`RequestId`], and the error event is still emitted if they fail - see
+/// [`TracingLoggerBuilder::error_event_policy`].
+/// Sampled requests record the reason they were sampled in the `sampling.decision` field of
+/// their root span - either `parent` or `ratio`.
+///
+/// # Usage
+///
+/// ```rust
+/// use tracing_actix_web::{PathPattern, SamplingPolicy, TracingLogger};
+///
+/// // Sample 1% of all requests, but 10% of those hitting the checkout API.
+/// let sampling = SamplingPolicy::ratio(0.01)
+///     .route_ratio(PathPattern::prefix("/api/checkout"), 0.1);
+/// let logger = TracingLogger::builder().sampling(sampling).build();
+/// ```
+///
+/// [`TracingLogger`]: crate::TracingLogger
+/// [`TracingLoggerBuilder::sampling`]: crate::TracingLoggerBuilder::sampling
+/// [`TracingLoggerBuilder::error_event_policy`]: crate::TracingLoggerBuilder::error_event_policy
+/// [`Span::none`]: tracing::Span::none
+/// [`RequestId`]: crate::RequestId
+#[derive(Clone, Debug)]
+pub struct SamplingPolicy {
+    ratio: f64,
+    route_ratios: Vec<(PathPattern, f64)>,
+    respect_parent_decision: bool,
+}
+
+impl SamplingPolicy {
+    /// Sample requests with the specified ratio, between `0.0` (never) and `1.0` (always).
+    pub fn ratio(ratio: f64) -> Self {
+        Self {
+            ratio,
+            route_ratios: Vec::new(),
+            respect_parent_decision: true,
+        }
+    }
+
+    /// Sample requests matching `pattern` with a dedicated ratio.
+    ///
+    /// You can call this method more than once: rules are evaluated in the order they
+    /// were specified and the first match wins.
+    pub fn route_ratio(mut self, pattern: PathPattern, ratio: f64) -> Self {
+        self.route_ratios.push((pattern, ratio));
+        self
+    }
+
+    /// Always sample requests whose `traceparent` header has the `sampled` flag set.
+    ///
+    /// It defaults to `true`.
+    pub fn respect_parent_decision(mut self, enabled: bool) -> Self {
+        self.respect_parent_decision = enabled;
+        self
+    }
+
+    pub(crate) fn sample(&self, request: &ServiceRequest) -> SamplingDecision {
+        if self.respect_parent_decision && is_parent_sampled(request) {
+            return SamplingDecision::Parent;
+        }
+        let ratio = self
+            .route_ratios
+            .iter()
+            .find(|(pattern, _)| pattern.matches_request(request.request()))
+            .map_or(self.ratio, |(_, ratio)| *ratio);
+        if random_ratio() < ratio {
+            SamplingDecision::Ratio
+        } else {
+            SamplingDecision::Drop
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum SamplingDecision {
+    /// Sampled, because the upstream caller sampled the trace.
+    Parent,
+    /// Sampled, according to the configured ratio.
+    Ratio,
+    /// Not sampled.
+    Drop,
+}
+
+impl SamplingDecision {
+    pub(crate) fn is_sampled(self) -> bool {
+        !matches!(self, SamplingDecision::Drop)
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SamplingDecision::Parent => "parent",
+            SamplingDecision::Ratio => "ratio",
+            SamplingDecision::Drop => "drop",
+        }
+    }
+}
+
+/// `true` if the request carries a valid `traceparent` header with the `sampled` flag set.
+fn is_parent_sampled(request: &ServiceRequest) -> bool {
+    let traceparent = match request
+        .headers()
+        .get("traceparent")
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(traceparent) => traceparent,
+        None => return false,
+    };
+    // version-trace_id-parent_id-flags, e.g. 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01
+    let parts: Vec<&str> = traceparent.trim().split('-').collect();
+    match parts.as_slice() {
+        [version, trace_id, parent_id, flags, ..]
+            if version.len() == 2 && trace_id.len() == 32 && parent_id.len() == 16 =>
+        {
+            u8::from_str_radix(flags, 16).is_ok_and(|flags| flags & 0x01 == 0x01)
+        }
+        _ => false,
+    }
+}
+
+/// A uniformly distributed value in `[0, 1)`.
+///
+/// Sampling does not require cryptographic randomness: we use a thread-local xorshift
+/// generator, seeded using the random keys of the standard library's `RandomState`.
+fn random_ratio() -> f64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+    }
+    let value = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
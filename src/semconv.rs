@@ -0,0 +1,91 @@
+/// `SemanticConventions` determines the names of the HTTP fields recorded on the root span
+/// by [`root_span!`] and [`DefaultRootSpanBuilder`].
+///
+/// OpenTelemetry renamed most HTTP attributes when it stabilised its HTTP semantic
+/// conventions (v1.20 onwards):
+///
+/// | Legacy             | Stable                                        |
+/// |--------------------|-----------------------------------------------|
+/// | `http.method`      | `http.request.method`                         |
+/// | `http.flavor`      | `network.protocol.version`                    |
+/// | `http.scheme`      | `url.scheme`                                  |
+/// | `http.host`        | none - see `server.address` and `server.port` |
+/// | `http.client_ip`   | `client.address`                              |
+/// | `http.user_agent`  | `user_agent.original`                         |
+/// | `http.target`      | `url.path` and `url.query`                    |
+/// | `http.status_code` | `http.response.status_code`                   |
+///
+/// All other fields (e.g. `http.route`, `server.address`, `server.port` or
+/// `network.peer.address`) are recorded in every mode.
+///
+/// # Usage
+///
+/// ```rust
+/// use tracing_actix_web::{SemanticConventions, TracingLogger};
+///
+/// let logger = TracingLogger::builder()
+///     .semantic_conventions(SemanticConventions::Stable)
+///     .build();
+/// ```
+///
+/// [`root_span!`]: crate::root_span!
+/// [`DefaultRootSpanBuilder`]: crate::DefaultRootSpanBuilder
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SemanticConventions {
+    /// The field names used by OpenTelemetry before v1.20 - e.g. `http.method`.
+    #[default]
+    Legacy,
+    /// The field names of OpenTelemetry's stable HTTP semantic conventions - e.g.
+    /// `http.request.method`.
+    Stable,
+    /// Both the legacy and the stable field names, e.g. `http.method` _and_
+    /// `http.request.method`.
+    ///
+    /// Use it to migrate your dashboards and alerts gradually.
+    Dup,
+}
+
+impl SemanticConventions {
+    /// Determine the semantic conventions using the `OTEL_SEMCONV_STABILITY_OPT_IN`
+    /// environment variable, following OpenTelemetry's migration guidelines:
+    ///
+    /// - `http/dup` selects [`SemanticConventions::Dup`];
+    /// - `http` selects [`SemanticConventions::Stable`];
+    /// - anything else (including a missing variable) selects [`SemanticConventions::Legacy`].
+    ///
+    /// The variable is a comma-separated list: `http/dup` takes precedence over `http`.
+    ///
+    /// ```rust
+    /// use tracing_actix_web::{SemanticConventions, TracingLogger};
+    ///
+    /// let logger = TracingLogger::builder()
+    ///     .semantic_conventions(SemanticConventions::from_env())
+    ///     .build();
+    /// ```
+    pub fn from_env() -> Self {
+        match std::env::var("OTEL_SEMCONV_STABILITY_OPT_IN") {
+            Ok(opt_in) => Self::from_opt_in(&opt_in),
+            Err(_) => Self::Legacy,
+        }
+    }
+
+    fn from_opt_in(opt_in: &str) -> Self {
+        let values: Vec<&str> = opt_in.split(',').map(str::trim).collect();
+        if values.contains(&"http/dup") {
+            Self::Dup
+        } else if values.contains(&"http") {
+            Self::Stable
+        } else {
+            Self::Legacy
+        }
+    }
+
+    pub(crate) fn emit_legacy(self) -> bool {
+        matches!(self, SemanticConventions::Legacy | SemanticConventions::Dup)
+    }
+
+    pub(crate) fn emit_stable(self) -> bool {
+        matches!(self, SemanticConventions::Stable | SemanticConventions::Dup)
+    }
+}
@@ -0,0 +1,240 @@
+use crate::config::RequestContext;
+use crate::PathPattern;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpRequest};
+
+/// `StatusClassifier` determines which requests count as failures, according to the
+/// status code of their response - see [`TracingLoggerBuilder::status_classifier`].
+///
+/// Its classification drives:
+///
+/// - the `otel.status_code` field recorded by [`DefaultRootSpanBuilder`] - `ERROR` for
+///   [`StatusClass::Failure`], `OK` for everything else;
+/// - the level of the error event - see [`ErrorEventPolicy::client_error_level`] and
+///   [`ErrorEventPolicy::server_error_level`];
+/// - which requests get a backtrace - see
+///   [`TracingLoggerBuilder::capture_server_error_backtraces`];
+/// - which requests are promoted to a summary event - see
+///   [`TracingLoggerBuilder::promote_failed_requests`].
+///
+/// By default, `1xx`, `2xx` and `3xx` responses are successes, `4xx` responses are client
+/// errors and everything else is a failure.  
+/// Use [`StatusClass::of`] to classify the outcome of a request in a custom
+/// [`RootSpanBuilder`](crate::RootSpanBuilder).
+///
+/// # Usage
+///
+/// ```rust
+/// use actix_web::http::StatusCode;
+/// use tracing_actix_web::{PathPattern, StatusClass, StatusClassifier, TracingLogger};
+///
+/// let classifier = StatusClassifier::new()
+///     // Our RPC-over-HTTP endpoints never expect a conflict...
+///     .route_status_class(PathPattern::prefix("/rpc/"), StatusCode::CONFLICT, StatusClass::Failure)
+///     // ...and nobody should ever hit our rate limits.
+///     .status_class(StatusCode::TOO_MANY_REQUESTS, StatusClass::Failure);
+///
+/// assert_eq!(classifier.classify(StatusCode::TOO_MANY_REQUESTS), StatusClass::Failure);
+/// assert_eq!(classifier.classify(StatusCode::CONFLICT), StatusClass::ClientError);
+///
+/// let logger = TracingLogger::builder()
+///     .status_classifier(classifier)
+///     .build();
+/// ```
+///
+/// [`TracingLoggerBuilder::status_classifier`]: crate::TracingLoggerBuilder::status_classifier
+/// [`TracingLoggerBuilder::capture_server_error_backtraces`]: crate::TracingLoggerBuilder::capture_server_error_backtraces
+/// [`TracingLoggerBuilder::promote_failed_requests`]: crate::TracingLoggerBuilder::promote_failed_requests
+/// [`DefaultRootSpanBuilder`]: crate::DefaultRootSpanBuilder
+/// [`ErrorEventPolicy::client_error_level`]: crate::ErrorEventPolicy::client_error_level
+/// [`ErrorEventPolicy::server_error_level`]: crate::ErrorEventPolicy::server_error_level
+#[derive(Clone, Debug, Default)]
+pub struct StatusClassifier {
+    status_classes: Vec<(StatusCode, StatusClass)>,
+    route_status_classes: Vec<(PathPattern, Vec<(StatusCode, StatusClass)>)>,
+}
+
+/// The class of a request, as determined by a [`StatusClassifier`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusClass {
+    /// The request was processed successfully.
+    Success,
+    /// The caller did something wrong: the server behaved as expected.
+    ClientError,
+    /// The server failed to process the request.
+    Failure,
+}
+
+impl StatusClass {
+    /// The class of the outcome of a request processed by [`TracingLogger`], according to
+    /// the [`StatusClassifier`] it was configured with - e.g. to classify requests in
+    /// [`RootSpanBuilder::on_request_end`].
+    ///
+    /// Route-specific rules are taken into account even if the outcome is an `Err`.
+    /// Requests that are not processed by [`TracingLogger`] are classified by
+    /// [`StatusClassifier::new`].
+    ///
+    /// [`TracingLogger`]: crate::TracingLogger
+    /// [`RootSpanBuilder::on_request_end`]: crate::RootSpanBuilder::on_request_end
+    pub fn of<B>(outcome: &Result<ServiceResponse<B>, Error>) -> Self {
+        let status_code = match outcome {
+            Ok(response) => response.status(),
+            Err(error) => error.as_response_error().status_code(),
+        };
+        RequestContext::of_outcome(outcome).status_class(status_code)
+    }
+}
+
+impl StatusClassifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify responses with the specified status code, for all routes.
+    ///
+    /// If the same status code is classified more than once, the last call wins.
+    pub fn status_class(mut self, status_code: StatusCode, class: StatusClass) -> Self {
+        self.status_classes.push((status_code, class));
+        self
+    }
+
+    /// Classify responses with the specified status code for requests matching `pattern`.
+    ///
+    /// Route-specific rules take precedence over the ones specified via
+    /// [`StatusClassifier::status_class`]. Patterns are evaluated in the order they
+    /// were first specified and only the first matching one is used.
+    pub fn route_status_class(
+        mut self,
+        pattern: PathPattern,
+        status_code: StatusCode,
+        class: StatusClass,
+    ) -> Self {
+        match self
+            .route_status_classes
+            .iter_mut()
+            .find(|(p, _)| *p == pattern)
+        {
+            Some((_, status_classes)) => status_classes.push((status_code, class)),
+            None => self
+                .route_status_classes
+                .push((pattern, vec![(status_code, class)])),
+        }
+        self
+    }
+
+    /// Classify a status code, ignoring route-specific rules.
+    pub fn classify(&self, status_code: StatusCode) -> StatusClass {
+        find_class(&self.status_classes, status_code).unwrap_or_else(|| {
+            if status_code.is_client_error() {
+                StatusClass::ClientError
+            } else if status_code.is_server_error() {
+                StatusClass::Failure
+            } else if status_code.is_informational()
+                || status_code.is_success()
+                || status_code.is_redirection()
+            {
+                StatusClass::Success
+            } else {
+                StatusClass::Failure
+            }
+        })
+    }
+
+    /// Classify the status code of the response to `request`, taking route-specific rules
+    /// into account.
+    pub fn classify_request(&self, request: &HttpRequest, status_code: StatusCode) -> StatusClass {
+        self.classify_route(self.route(request), status_code)
+    }
+
+    /// The index of the route-specific rules that apply to `request`, if any.
+    ///
+    /// It is determined when the request comes in and stored in its [`RequestContext`]: the
+    /// outcome of the request might not carry the request anymore (e.g. if a middleware fails).
+    pub(crate) fn route(&self, request: &HttpRequest) -> Option<usize> {
+        self.route_status_classes
+            .iter()
+            .position(|(pattern, _)| pattern.matches_request(request))
+    }
+
+    /// Classify a status code, using the route-specific rules at index `route`, if any.
+    pub(crate) fn classify_route(
+        &self,
+        route: Option<usize>,
+        status_code: StatusCode,
+    ) -> StatusClass {
+        route
+            .and_then(|route| self.route_status_classes.get(route))
+            .and_then(|(_, status_classes)| find_class(status_classes, status_code))
+            .unwrap_or_else(|| self.classify(status_code))
+    }
+}
+
+fn find_class(
+    status_classes: &[(StatusCode, StatusClass)],
+    status_code: StatusCode,
+) -> Option<StatusClass> {
+    // Later overrides win.
+    status_classes
+        .iter()
+        .rev()
+        .find(|(code, _)| *code == status_code)
+        .map(|(_, class)| *class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::{ErrorInternalServerError, ErrorNotFound};
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn status_codes_are_classified_by_their_class_by_default() {
+        let classifier = StatusClassifier::new();
+        assert_eq!(classifier.classify(StatusCode::OK), StatusClass::Success);
+        assert_eq!(classifier.classify(StatusCode::FOUND), StatusClass::Success);
+        assert_eq!(
+            classifier.classify(StatusCode::NOT_FOUND),
+            StatusClass::ClientError
+        );
+        assert_eq!(
+            classifier.classify(StatusCode::INTERNAL_SERVER_ERROR),
+            StatusClass::Failure
+        );
+    }
+
+    #[test]
+    fn route_specific_rules_take_precedence() {
+        let classifier = StatusClassifier::new()
+            .status_class(StatusCode::CONFLICT, StatusClass::Failure)
+            .route_status_class(
+                PathPattern::prefix("/rpc/"),
+                StatusCode::CONFLICT,
+                StatusClass::ClientError,
+            );
+        let rpc = TestRequest::with_uri("/rpc/call").to_http_request();
+        let other = TestRequest::with_uri("/users").to_http_request();
+        assert_eq!(
+            classifier.classify_request(&rpc, StatusCode::CONFLICT),
+            StatusClass::ClientError
+        );
+        assert_eq!(
+            classifier.classify_request(&other, StatusCode::CONFLICT),
+            StatusClass::Failure
+        );
+        // Status codes without route-specific rules fall back to the global ones.
+        assert_eq!(
+            classifier.classify_request(&rpc, StatusCode::BAD_GATEWAY),
+            StatusClass::Failure
+        );
+    }
+
+    #[test]
+    fn errors_are_classified_outside_of_the_middleware() {
+        let server_error: Result<ServiceResponse<()>, Error> =
+            Err(ErrorInternalServerError("boom"));
+        assert_eq!(StatusClass::of(&server_error), StatusClass::Failure);
+        let client_error: Result<ServiceResponse<()>, Error> = Err(ErrorNotFound("missing"));
+        assert_eq!(StatusClass::of(&client_error), StatusClass::ClientError);
+    }
+}
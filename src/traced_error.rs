@@ -0,0 +1,121 @@
+use actix_web::{Error, ResponseError};
+use std::backtrace::Backtrace;
+use std::borrow::Cow;
+use tracing::Span;
+
+/// `TracedError` lets your error types expose structured details to [`DefaultRootSpanBuilder`],
+/// on top of the `Display` and `Debug` representations recorded in `exception.message` and
+/// `exception.details`:
+///
+/// - the type of the error (`error.type`);
+/// - an application-specific error code (`error.code`);
+/// - whether the failed operation can be retried (`error.retryable`).
+///
+/// `actix-web` erases the type of your errors when they are converted into an
+/// [`actix_web::Error`]: you must register each type implementing `TracedError` using
+/// [`TracingLoggerBuilder::traced_error`], so that it can be detected when a request fails.  
+/// Registering it also makes the causes of your errors reachable: the chain of
+/// [`std::error::Error::source`]s is recorded in `exception.chain` - see
+/// [`TracingLoggerBuilder::exception_chain_max_depth`]. Without a registration, the causes
+/// can only be reached for `Box<dyn std::error::Error>` and `std::io::Error`.
+///
+/// # Usage
+///
+/// ```rust
+/// use actix_web::ResponseError;
+/// use std::borrow::Cow;
+/// use tracing_actix_web::{TracedError, TracingLogger};
+///
+/// #[derive(Debug)]
+/// pub enum CheckoutError {
+///     PaymentDeclined,
+///     PaymentProviderUnavailable(std::io::Error),
+/// }
+///
+/// impl std::fmt::Display for CheckoutError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         match self {
+///             CheckoutError::PaymentDeclined => write!(f, "The payment was declined"),
+///             CheckoutError::PaymentProviderUnavailable(_) => write!(f, "The payment provider is unavailable"),
+///         }
+///     }
+/// }
+///
+/// impl std::error::Error for CheckoutError {
+///     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+///         match self {
+///             CheckoutError::PaymentDeclined => None,
+///             CheckoutError::PaymentProviderUnavailable(e) => Some(e),
+///         }
+///     }
+/// }
+///
+/// impl ResponseError for CheckoutError {}
+///
+/// impl TracedError for CheckoutError {
+///     fn error_code(&self) -> Option<Cow<'_, str>> {
+///         match self {
+///             CheckoutError::PaymentDeclined => Some("CHK-001".into()),
+///             CheckoutError::PaymentProviderUnavailable(_) => Some("CHK-002".into()),
+///         }
+///     }
+///
+///     fn is_retryable(&self) -> Option<bool> {
+///         Some(matches!(self, CheckoutError::PaymentProviderUnavailable(_)))
+///     }
+/// }
+///
+/// let logger = TracingLogger::builder()
+///     .traced_error::<CheckoutError>()
+///     .build();
+/// ```
+///
+/// [`DefaultRootSpanBuilder`]: crate::DefaultRootSpanBuilder
+/// [`TracingLoggerBuilder::traced_error`]: crate::TracingLoggerBuilder::traced_error
+/// [`TracingLoggerBuilder::exception_chain_max_depth`]: crate::TracingLoggerBuilder::exception_chain_max_depth
+pub trait TracedError: ResponseError + std::error::Error + 'static {
+    /// The type of the error, recorded in `error.type`.
+    ///
+    /// It defaults to the name of the Rust type.
+    fn error_type(&self) -> Cow<'static, str> {
+        std::any::type_name::<Self>().into()
+    }
+
+    /// An application-specific error code, recorded in `error.code`.
+    fn error_code(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// Whether the failed operation can be retried, recorded in `error.retryable`.
+    fn is_retryable(&self) -> Option<bool> {
+        None
+    }
+
+    /// The backtrace captured when the error was created, recorded in `exception.stacktrace`
+    /// for server errors - see [`TracingLoggerBuilder::capture_server_error_backtraces`].
+    ///
+    /// E.g. for a wrapper around `anyhow::Error`, return `Some(self.0.backtrace())`.
+    ///
+    /// [`TracingLoggerBuilder::capture_server_error_backtraces`]: crate::TracingLoggerBuilder::capture_server_error_backtraces
+    fn backtrace(&self) -> Option<&Backtrace> {
+        None
+    }
+}
+
+/// Recovers a [`TracedError`] from a type-erased [`actix_web::Error`].
+pub(crate) type TracedErrorDowncaster = for<'a> fn(&'a Error) -> Option<&'a dyn TracedError>;
+
+pub(crate) fn downcast<E: TracedError>(error: &Error) -> Option<&dyn TracedError> {
+    error.as_error::<E>().map(|e| e as &dyn TracedError)
+}
+
+/// Record the details exposed by `error` on the root span.
+pub(crate) fn record_traced_error(span: &Span, error: &dyn TracedError) {
+    span.record("error.type", tracing::field::display(error.error_type()));
+    if let Some(code) = error.error_code() {
+        span.record("error.code", tracing::field::display(code));
+    }
+    if let Some(is_retryable) = error.is_retryable() {
+        span.record("error.retryable", is_retryable);
+    }
+}
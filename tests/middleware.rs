@@ -0,0 +1,440 @@
+use actix_web::body::{to_bytes, BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorInternalServerError, ErrorServiceUnavailable};
+use actix_web::http::header::HeaderName;
+use actix_web::test::{call_service, init_service, read_body, try_call_service, TestRequest};
+use actix_web::web::Bytes;
+use actix_web::{web, App, Error, HttpResponse};
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Span, Subscriber};
+use tracing_actix_web::{
+    BodyEnd, BodyOutcome, DefaultRootSpanBuilder, PathPattern, RootSpanBuilder, SamplingPolicy,
+    TracingLogger,
+};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+/// The fields recorded on a span or an event, rendered as strings.
+type Fields = HashMap<String, String>;
+
+/// A [`Layer`] that keeps track of the closed spans and of the events it sees.
+#[derive(Clone, Default)]
+struct Recorder {
+    open_spans: Arc<Mutex<HashMap<Id, Fields>>>,
+    spans: Arc<Mutex<Vec<Fields>>>,
+    events: Arc<Mutex<Vec<(Level, Fields)>>>,
+}
+
+impl Recorder {
+    /// Record what happens on the current thread until the guard is dropped.
+    fn install(&self) -> tracing::subscriber::DefaultGuard {
+        tracing::subscriber::set_default(Registry::default().with(self.clone()))
+    }
+
+    fn spans(&self) -> Vec<Fields> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    fn events(&self) -> Vec<(Level, Fields)> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{value:?}"));
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        self.open_spans.lock().unwrap().insert(id.clone(), fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+        if let Some(fields) = self.open_spans.lock().unwrap().get_mut(id) {
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        self.events
+            .lock()
+            .unwrap()
+            .push((*event.metadata().level(), fields));
+    }
+
+    fn on_close(&self, id: Id, _ctx: Context<'_, S>) {
+        if let Some(fields) = self.open_spans.lock().unwrap().remove(&id) {
+            self.spans.lock().unwrap().push(fields);
+        }
+    }
+}
+
+async fn fail() -> Result<String, Error> {
+    Err(ErrorInternalServerError("boom"))
+}
+
+async fn explode() -> String {
+    panic!("kaboom")
+}
+
+/// Polls the wrapped future, catching the panics it raises.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        match catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+static BODY_SIZE: Mutex<Option<BodySize>> = Mutex::new(None);
+
+struct BodySizeRootSpanBuilder;
+
+impl RootSpanBuilder for BodySizeRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        DefaultRootSpanBuilder::on_request_start(request)
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        if let Ok(response) = outcome {
+            *BODY_SIZE.lock().unwrap() = Some(response.response().body().size());
+        }
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+#[actix_web::test]
+async fn root_span_builders_get_the_response_with_its_body() {
+    let app = init_service(
+        App::new()
+            .wrap(TracingLogger::<BodySizeRootSpanBuilder>::new())
+            .route("/", web::get().to(|| async { "hello world" })),
+    )
+    .await;
+    call_service(&app, TestRequest::get().uri("/").to_request()).await;
+    assert_eq!(*BODY_SIZE.lock().unwrap(), Some(BodySize::Sized(11)));
+}
+
+#[actix_web::test]
+async fn requests_that_are_not_sampled_still_emit_the_error_event() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(
+                TracingLogger::builder()
+                    .sampling(SamplingPolicy::ratio(0.0))
+                    .build(),
+            )
+            .route("/", web::get().to(fail)),
+    )
+    .await;
+    let response = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+    assert_eq!(response.status(), 500);
+    read_body(response).await;
+
+    assert!(recorder.spans().is_empty());
+    let events = recorder.events();
+    assert_eq!(events.len(), 1);
+    let (level, fields) = &events[0];
+    assert_eq!(*level, Level::ERROR);
+    assert!(fields["message"].contains("boom"), "{:?}", fields);
+}
+
+#[actix_web::test]
+async fn failed_requests_are_promoted_with_the_fields_of_the_root_span() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(
+                TracingLogger::builder()
+                    .span_level(Level::DEBUG)
+                    .promote_failed_requests(true)
+                    .build(),
+            )
+            .route("/fail", web::get().to(fail)),
+    )
+    .await;
+    let request = TestRequest::get()
+        .uri("/fail?lang=en")
+        .peer_addr("10.0.0.1:4242".parse().unwrap())
+        .to_request();
+    read_body(call_service(&app, request).await).await;
+
+    let spans = recorder.spans();
+    let span = &spans[0];
+    let events = recorder.events();
+    let (level, event) = events
+        .iter()
+        .find(|(_, fields)| fields["message"] == "HTTP request failed")
+        .unwrap();
+    assert_eq!(*level, Level::WARN);
+    for field in ["http.route", "http.target", "http.client_ip", "request_id"] {
+        assert_eq!(event[field], span[field], "{}", field);
+    }
+    assert_eq!(event["http.status_code"], "500");
+    assert_eq!(event["otel.status_code"], "ERROR");
+}
+
+/// A response body that fails after its first chunk.
+struct FailingBody {
+    is_started: bool,
+}
+
+impl MessageBody for FailingBody {
+    type Error = std::io::Error;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        if self.is_started {
+            return Poll::Ready(Some(Err(std::io::Error::other("connection reset"))));
+        }
+        self.is_started = true;
+        Poll::Ready(Some(Ok(Bytes::from_static(b"hello"))))
+    }
+}
+
+#[actix_web::test]
+async fn body_errors_are_recorded_on_the_root_span() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(App::new().wrap(TracingLogger::default()).route(
+        "/",
+        web::get().to(|| async { HttpResponse::Ok().body(FailingBody { is_started: false }) }),
+    ))
+    .await;
+    let response = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+    assert!(to_bytes(response.into_body()).await.is_err());
+
+    let span = &recorder.spans()[0];
+    assert_eq!(span["http.response.body.size"], "5");
+    assert_eq!(span["http.response.body.outcome"], "failed");
+    assert_eq!(span["http.response.body.error"], "connection reset");
+}
+
+#[actix_web::test]
+async fn headers_captured_more_than_once_are_recorded_once() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(
+                TracingLogger::builder()
+                    .capture_request_header(HeaderName::from_static("x-tenant-id"))
+                    .capture_request_header(HeaderName::from_static("x-tenant-id"))
+                    .build(),
+            )
+            .route("/", web::get().to(|| async { "hello world" })),
+    )
+    .await;
+    let request = TestRequest::get()
+        .uri("/")
+        .insert_header(("x-tenant-id", "acme"))
+        .to_request();
+    read_body(call_service(&app, request).await).await;
+
+    let span = &recorder.spans()[0];
+    assert_eq!(span["http.request.headers"], r#"{"x-tenant-id": ["acme"]}"#);
+}
+
+static BODY_END: Mutex<Option<(BodyOutcome, u64)>> = Mutex::new(None);
+
+struct BodyEndRootSpanBuilder;
+
+impl RootSpanBuilder for BodyEndRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        DefaultRootSpanBuilder::on_request_start(request)
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+
+    fn on_body_end(_span: Span, body_end: &BodyEnd) {
+        *BODY_END.lock().unwrap() = Some((*body_end.outcome(), body_end.bytes_sent()));
+    }
+}
+
+#[actix_web::test]
+async fn the_end_of_the_response_body_is_recorded() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(TracingLogger::<BodyEndRootSpanBuilder>::new())
+            .route("/", web::get().to(|| async { "hello world" })),
+    )
+    .await;
+    read_body(call_service(&app, TestRequest::get().uri("/").to_request()).await).await;
+
+    let span = &recorder.spans()[0];
+    assert_eq!(span["http.response.body.size"], "11");
+    assert_eq!(span["http.response.body.outcome"], "completed");
+    assert_eq!(
+        *BODY_END.lock().unwrap(),
+        Some((BodyOutcome::Completed, 11))
+    );
+}
+
+#[actix_web::test]
+async fn panics_are_recorded_and_resumed() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("/", web::get().to(explode)),
+    )
+    .await;
+    let request = TestRequest::get().uri("/").to_request();
+    let panic = CatchUnwind(Box::pin(call_service(&app, request)))
+        .await
+        .unwrap_err();
+    assert_eq!(panic.downcast_ref::<&str>(), Some(&"kaboom"));
+
+    let span = &recorder.spans()[0];
+    assert_eq!(span["exception.message"], "kaboom");
+    assert_eq!(span["otel.status_code"], "ERROR");
+    let events = recorder.events();
+    let (level, event) = &events[0];
+    assert_eq!(*level, Level::ERROR);
+    assert!(
+        event["message"].contains("the request handler panicked: kaboom"),
+        "{:?}",
+        event
+    );
+}
+
+#[actix_web::test]
+async fn the_request_id_header_is_set_on_error_responses() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    // The inner middleware fails without producing a response: `TracingLogger` sees an `Err`.
+    let app = init_service(
+        App::new()
+            .wrap_fn(|_, _| async { Err::<ServiceResponse, _>(ErrorServiceUnavailable("down")) })
+            .wrap(
+                TracingLogger::builder()
+                    .request_id_response_header(HeaderName::from_static("x-request-id"))
+                    .build(),
+            )
+            .route("/", web::get().to(|| async { "hello world" })),
+    )
+    .await;
+    let error = try_call_service(&app, TestRequest::get().uri("/").to_request())
+        .await
+        .unwrap_err();
+    let response = error.error_response();
+    assert_eq!(response.status(), 503);
+
+    let span = &recorder.spans()[0];
+    assert_eq!(
+        response
+            .headers()
+            .get("x-request-id")
+            .unwrap()
+            .to_str()
+            .unwrap(),
+        span["request_id"]
+    );
+}
+
+#[actix_web::test]
+async fn excluded_requests_are_neither_traced_nor_logged() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(
+                TracingLogger::builder()
+                    .exclude(PathPattern::exact("/health"))
+                    .build(),
+            )
+            .route("/health", web::get().to(fail)),
+    )
+    .await;
+    let response = call_service(&app, TestRequest::get().uri("/health").to_request()).await;
+    assert_eq!(response.status(), 500);
+    read_body(response).await;
+
+    assert!(recorder.spans().is_empty());
+    assert!(recorder.events().is_empty());
+}
+
+#[actix_web::test]
+async fn sampled_requests_record_the_sampling_decision() {
+    let recorder = Recorder::default();
+    let _guard = recorder.install();
+    let app = init_service(
+        App::new()
+            .wrap(
+                TracingLogger::builder()
+                    .sampling(SamplingPolicy::ratio(0.0).route_ratio(PathPattern::exact("/"), 1.0))
+                    .build(),
+            )
+            .route("/", web::get().to(|| async { "hello world" }))
+            .route("/other", web::get().to(|| async { "hello world" })),
+    )
+    .await;
+    read_body(call_service(&app, TestRequest::get().uri("/").to_request()).await).await;
+    read_body(call_service(&app, TestRequest::get().uri("/other").to_request()).await).await;
+    let request = TestRequest::get()
+        .uri("/other")
+        .insert_header((
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ))
+        .to_request();
+    read_body(call_service(&app, request).await).await;
+
+    let decisions: Vec<_> = recorder
+        .spans()
+        .iter()
+        .map(|span| {
+            (
+                span["http.target"].clone(),
+                span["sampling.decision"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        decisions,
+        [
+            ("/".to_owned(), "ratio".to_owned()),
+            ("/other".to_owned(), "parent".to_owned())
+        ]
+    );
+}